@@ -0,0 +1,506 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::sanitize_git_path_component;
+
+const DEFAULT_FOLDER_PATTERN: &str = "{docname}.assets";
+const DEFAULT_FILE_NAME_PATTERN: &str = "{timestamp}-{docname}-{filename}";
+/// Placeholders whose value differs from one saved image to the next.
+const PER_SAVE_PLACEHOLDERS: [&str; 8] = [
+    "{filename}",
+    "{date}",
+    "{year}",
+    "{month}",
+    "{day}",
+    "{timestamp}",
+    "{hash}",
+    "{n}",
+];
+
+/// Where the asset folder pattern is resolved from.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AssetBase {
+    /// Relative to the directory containing the document.
+    #[default]
+    Document,
+    /// Relative to the workspace root (`workspacePath`).
+    Workspace,
+}
+
+/// Asset layout options shared by the document image commands.
+///
+/// Folder and file name patterns support `{docname}`, `{slug}`, `{filename}`,
+/// `{date}`, `{year}`, `{month}`, `{day}`, `{timestamp}`, `{hash}` and `{n}`.
+/// The defaults reproduce the historical `<stem>.assets/<millis>-<stem>-<source>` layout.
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct AssetLayout {
+    pub base: AssetBase,
+    pub workspace_path: Option<String>,
+    pub folder: Option<String>,
+    pub file_name: Option<String>,
+    /// Return a document-relative, Markdown-ready link instead of the absolute path.
+    pub relative_link: bool,
+    #[serde(flatten)]
+    pub naming: AssetNaming,
+}
+
+/// Content-addressed naming and deduplication, shared by every image store.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct AssetNaming {
+    /// Name files by content hash (`{hash}`) instead of the configured pattern.
+    pub hash_names: bool,
+    /// Reuse an identical file already present in the target folder.
+    pub dedupe: bool,
+}
+
+/// A resolved asset path. `existing` is set when deduplication matched a file
+/// that already holds the same bytes, in which case nothing should be written.
+pub(crate) struct ResolvedAsset {
+    pub path: PathBuf,
+    pub existing: bool,
+}
+
+struct PatternContext<'a> {
+    doc_name: String,
+    slug: String,
+    source_stem: String,
+    date: CivilDate,
+    timestamp: u128,
+    content: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct CivilDate {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// Resolves the destination path for an asset belonging to `document`,
+/// creating the asset folder. A new path never overwrites an existing file.
+pub(crate) fn resolve_asset_destination(
+    layout: &AssetLayout,
+    document: &Path,
+    source_stem: Option<&str>,
+    extension: &str,
+    content: &[u8],
+) -> Result<ResolvedAsset, String> {
+    let ctx = PatternContext::new(document, source_stem, content)?;
+    let base_dir = asset_base_dir(layout, document)?;
+    let assets_dir = base_dir.join(expand_folder_pattern(folder_pattern(layout), &ctx)?);
+    std::fs::create_dir_all(&assets_dir).map_err(|e| e.to_string())?;
+
+    let name_pattern = if layout.naming.hash_names {
+        "{hash}"
+    } else {
+        layout
+            .file_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .unwrap_or(DEFAULT_FILE_NAME_PATTERN)
+    };
+    let extension = sanitize_git_path_component(&extension.to_ascii_lowercase());
+
+    if layout.naming.dedupe {
+        let preferred = assets_dir.join(format!(
+            "{}.{}",
+            sanitize_git_path_component(&expand_pattern(name_pattern, &ctx, None)),
+            extension
+        ));
+        if let Some(path) = find_duplicate(&assets_dir, Some(&preferred), content)? {
+            return Ok(ResolvedAsset { path, existing: true });
+        }
+    }
+
+    let path = pick_free_file_name(&assets_dir, name_pattern, &extension, &ctx)?;
+    Ok(ResolvedAsset {
+        path,
+        existing: false,
+    })
+}
+
+/// Existing asset folders of `document` under `layout`. Placeholders that
+/// change between saves (`{date}`, `{timestamp}`, `{hash}`, ...) match any
+/// folder name, so date-based folders from earlier days are found too.
+pub(crate) fn existing_asset_folders(
+    layout: &AssetLayout,
+    document: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let ctx = PatternContext::new(document, None, &[])?;
+    let mut folders = vec![asset_base_dir(layout, document)?];
+    for segment in folder_segments(folder_pattern(layout))? {
+        let mut wildcard = segment.to_string();
+        for key in PER_SAVE_PLACEHOLDERS {
+            wildcard = wildcard.replace(key, "\0");
+        }
+        let expanded = expand_pattern(&wildcard, &ctx, None);
+        if !expanded.contains('\0') {
+            let name = sanitize_git_path_component(&expanded);
+            folders = folders.into_iter().map(|dir| dir.join(&name)).collect();
+            continue;
+        }
+
+        // Sanitize each literal piece the way the whole name is sanitized on save.
+        let sanitized: String = expanded
+            .chars()
+            .map(|ch| {
+                if ch == '\0' || ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_') {
+                    ch
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let pieces: Vec<&str> = sanitized.trim_matches('_').split('\0').collect();
+        let mut matched = Vec::new();
+        for dir in &folders {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let name = entry.file_name();
+                if path.is_dir() && wildcard_matches(&pieces, &name.to_string_lossy()) {
+                    matched.push(path);
+                }
+            }
+        }
+        folders = matched;
+    }
+    folders.retain(|dir| dir.is_dir());
+    Ok(folders)
+}
+
+// Whether `name` is `pieces` joined by runs of any characters.
+fn wildcard_matches(pieces: &[&str], name: &str) -> bool {
+    let (Some(first), Some(last)) = (pieces.first(), pieces.last()) else {
+        return false;
+    };
+    if pieces.len() < 2 {
+        return *first == name;
+    }
+    if name.len() < first.len() + last.len()
+        || !name.starts_with(first)
+        || !name.ends_with(last)
+    {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for piece in &pieces[1..pieces.len() - 1] {
+        match rest.find(piece) {
+            Some(at) => rest = &rest[at + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Resolves the destination for an image stored in a flat folder such as
+/// `carbo-assets/images`, named `<millis>-<file_name>` or `<hash>.<ext>`.
+pub(crate) fn resolve_store_destination(
+    dir: &Path,
+    file_name: &str,
+    naming: AssetNaming,
+    content: &[u8],
+) -> Result<ResolvedAsset, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let name = if naming.hash_names {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| sanitize_git_path_component(&s.to_ascii_lowercase()))
+            .unwrap_or_else(|| "bin".to_string());
+        format!("{}.{}", content_hash(content), extension)
+    } else {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_millis();
+        format!("{}-{}", ts, file_name)
+    };
+    let path = dir.join(name);
+
+    if naming.dedupe {
+        if let Some(existing) = find_duplicate(dir, Some(&path), content)? {
+            return Ok(ResolvedAsset {
+                path: existing,
+                existing: true,
+            });
+        }
+    }
+
+    Ok(ResolvedAsset {
+        existing: false,
+        path,
+    })
+}
+
+/// Looks for a file in `dir` whose bytes equal `content`, checking `preferred`
+/// first and then any file of the same size.
+fn find_duplicate(
+    dir: &Path,
+    preferred: Option<&Path>,
+    content: &[u8],
+) -> Result<Option<PathBuf>, String> {
+    let same_bytes = |path: &Path| -> bool {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.is_file() && meta.len() == content.len() as u64 => {
+                std::fs::read(path).map(|b| b == content).unwrap_or(false)
+            }
+            _ => false,
+        }
+    };
+
+    if let Some(preferred) = preferred {
+        if same_bytes(preferred) {
+            return Ok(Some(preferred.to_path_buf()));
+        }
+    }
+
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if Some(path.as_path()) == preferred {
+            continue;
+        }
+        if same_bytes(&path) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Formats `asset` as it should be returned to the webview for `layout`.
+pub(crate) fn asset_output_path(layout: &AssetLayout, document: &Path, asset: &Path) -> String {
+    if !layout.relative_link {
+        return asset.to_string_lossy().to_string();
+    }
+    let from = document.parent().unwrap_or_else(|| Path::new(""));
+    match relative_path(from, asset) {
+        Some(rel) => markdown_link_target(&rel),
+        None => asset.to_string_lossy().to_string(),
+    }
+}
+
+impl<'a> PatternContext<'a> {
+    fn new(document: &Path, source_stem: Option<&str>, content: &'a [u8]) -> Result<Self, String> {
+        let doc_stem = document
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|s| !s.is_empty())
+            .unwrap_or("document");
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_millis();
+        Ok(PatternContext {
+            doc_name: sanitize_git_path_component(doc_stem),
+            slug: slugify(doc_stem),
+            source_stem: sanitize_git_path_component(source_stem.unwrap_or(doc_stem)),
+            date: civil_date_from_millis(timestamp),
+            timestamp,
+            content,
+        })
+    }
+}
+
+fn asset_base_dir(layout: &AssetLayout, document: &Path) -> Result<PathBuf, String> {
+    match layout.base {
+        AssetBase::Document => document
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| "document has no parent directory".to_string()),
+        AssetBase::Workspace => {
+            let root = layout
+                .workspace_path
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| "workspace asset layout requires a workspace path".to_string())?;
+            Ok(PathBuf::from(root))
+        }
+    }
+}
+
+fn folder_pattern(layout: &AssetLayout) -> &str {
+    layout
+        .folder
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(DEFAULT_FOLDER_PATTERN)
+}
+
+fn folder_segments(pattern: &str) -> Result<Vec<&str>, String> {
+    let mut out = Vec::new();
+    for segment in pattern.split(['/', '\\']) {
+        let segment = segment.trim();
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment == ".." {
+            return Err("asset folder pattern must not contain '..'".to_string());
+        }
+        out.push(segment);
+    }
+    Ok(out)
+}
+
+fn expand_folder_pattern(pattern: &str, ctx: &PatternContext<'_>) -> Result<PathBuf, String> {
+    let mut out = PathBuf::new();
+    for segment in folder_segments(pattern)? {
+        let expanded = expand_pattern(segment, ctx, None);
+        out.push(sanitize_git_path_component(&expanded));
+    }
+    Ok(out)
+}
+
+fn pick_free_file_name(
+    dir: &Path,
+    pattern: &str,
+    extension: &str,
+    ctx: &PatternContext<'_>,
+) -> Result<PathBuf, String> {
+    let has_counter = pattern.contains("{n}");
+    for n in 0..10_000u32 {
+        let stem = if has_counter {
+            expand_pattern(pattern, ctx, Some(n + 1))
+        } else if n == 0 {
+            expand_pattern(pattern, ctx, None)
+        } else {
+            format!("{}-{}", expand_pattern(pattern, ctx, None), n)
+        };
+        let candidate = dir.join(format!("{}.{}", sanitize_git_path_component(&stem), extension));
+        if !candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+    Err("unable to find a free asset file name".to_string())
+}
+
+fn expand_pattern(pattern: &str, ctx: &PatternContext<'_>, n: Option<u32>) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let key = &after[..end];
+        match key {
+            "docname" => out.push_str(&ctx.doc_name),
+            "slug" => out.push_str(&ctx.slug),
+            "filename" => out.push_str(&ctx.source_stem),
+            "date" => out.push_str(&format!(
+                "{:04}-{:02}-{:02}",
+                ctx.date.year, ctx.date.month, ctx.date.day
+            )),
+            "year" => out.push_str(&format!("{:04}", ctx.date.year)),
+            "month" => out.push_str(&format!("{:02}", ctx.date.month)),
+            "day" => out.push_str(&format!("{:02}", ctx.date.day)),
+            "timestamp" => out.push_str(&ctx.timestamp.to_string()),
+            "hash" => out.push_str(&content_hash(ctx.content)),
+            "n" => out.push_str(&n.unwrap_or(1).to_string()),
+            _ => {
+                out.push('{');
+                out.push_str(key);
+                out.push('}');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// First 12 hex characters of the SHA-256 of `bytes`.
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    digest
+        .iter()
+        .take(6)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn slugify(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for ch in input.chars() {
+        if ch.is_ascii_alphanumeric() {
+            out.push(ch.to_ascii_lowercase());
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let trimmed = out.trim_matches('-');
+    if trimmed.is_empty() {
+        "document".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+// UTC calendar date for a unix timestamp, without pulling in a date crate.
+pub(crate) fn civil_date_from_millis(millis: u128) -> CivilDate {
+    let days = (millis / 86_400_000) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    CivilDate { year, month, day }
+}
+
+fn relative_path(from_dir: &Path, to: &Path) -> Option<PathBuf> {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    if from.first() != to_components.first() {
+        return None;
+    }
+    let common = from
+        .iter()
+        .zip(to_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut out = PathBuf::new();
+    for _ in common..from.len() {
+        out.push("..");
+    }
+    for component in &to_components[common..] {
+        out.push(component.as_os_str());
+    }
+    Some(out)
+}
+
+fn markdown_link_target(path: &Path) -> String {
+    let joined = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut out = String::with_capacity(joined.len());
+    for ch in joined.chars() {
+        match ch {
+            ' ' => out.push_str("%20"),
+            '(' => out.push_str("%28"),
+            ')' => out.push_str("%29"),
+            '<' => out.push_str("%3C"),
+            '>' => out.push_str("%3E"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+
+use crate::image_host::{self, HostConfig, ImageHost, UploadOptions};
+use crate::upload_cache::{self, UploadCache};
+use crate::upload_history::{self, HistoryEntry};
+use crate::{assets, credentials, markdown_images};
+
+const DEFAULT_CONCURRENCY: usize = 3;
+const MAX_CONCURRENCY: usize = 8;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct BatchUploadOptions {
+    pub max_bytes: u64,
+    /// Parallel uploads, capped at 8. Repository hosts always get one.
+    pub concurrency: usize,
+    #[serde(flatten)]
+    pub upload: UploadOptions,
+}
+
+impl Default for BatchUploadOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            concurrency: DEFAULT_CONCURRENCY,
+            upload: UploadOptions::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum UploadStatus {
+    Started,
+    Uploaded,
+    /// Already uploaded to this target, per the upload cache or the host.
+    Reused,
+    Failed,
+}
+
+/// Payload of the `carbo-upload-progress` event, sent once when a file starts
+/// and once when it finishes.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UploadProgress {
+    document_path: String,
+    local_path: String,
+    index: usize,
+    total: usize,
+    status: UploadStatus,
+    hosted_url: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UploadedImage {
+    local_path: String,
+    hosted_url: String,
+    reused: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UploadFailure {
+    /// Link target as written in the document.
+    url: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BatchUploadResult {
+    /// Document text with uploaded images linked to their hosted URLs.
+    text: String,
+    uploaded: Vec<UploadedImage>,
+    failures: Vec<UploadFailure>,
+}
+
+/// Hosted URL and whether an earlier upload was reused.
+type UploadOutcome = Result<(String, bool), String>;
+
+/// Uploads every local image referenced by the document to `host` and returns
+/// the text with those links rewritten. `text` defaults to the file on disk;
+/// the document itself is not written.
+#[tauri::command]
+pub(crate) async fn upload_document_images(
+    app: tauri::AppHandle,
+    document_path: String,
+    text: Option<String>,
+    host: HostConfig,
+    options: Option<BatchUploadOptions>,
+) -> Result<BatchUploadResult, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        upload_document_images_blocking(
+            &app,
+            document_path,
+            text,
+            host,
+            options.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn upload_document_images_blocking(
+    app: &tauri::AppHandle,
+    document_path: String,
+    text: Option<String>,
+    mut host: HostConfig,
+    mut options: BatchUploadOptions,
+) -> Result<BatchUploadResult, String> {
+    let document = PathBuf::from(&document_path);
+    let text = match text {
+        Some(text) => text,
+        None => std::fs::read_to_string(&document).map_err(|e| e.to_string())?,
+    };
+    credentials::fill_host_secret(app, &mut host)?;
+    let concurrency = worker_limit(&host, options.concurrency);
+    let host = image_host::build_host(app, &host, options.upload.timeout_secs)?;
+    let target_key = host.target_key();
+
+    let references: Vec<_> = markdown_images::find_image_references(&text)
+        .into_iter()
+        .filter_map(|r| {
+            let local = markdown_images::resolve_local_reference(&r.url, &document)?;
+            Some((r, local))
+        })
+        .collect();
+    let mut files: Vec<PathBuf> = Vec::new();
+    for (_, local) in &references {
+        if !files.contains(local) {
+            files.push(local.clone());
+        }
+    }
+
+    let cache = UploadCache::open(app)?;
+    let history = Mutex::new(Vec::new());
+    options.upload.document_path = Some(document_path.clone());
+
+    let total = files.len();
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<UploadOutcome>>> = Mutex::new((0..total).map(|_| None).collect());
+    let workers = concurrency.min(total.max(1));
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let progress = |status, hosted_url: Option<String>, error: Option<String>| {
+                    let _ = app.emit(
+                        "carbo-upload-progress",
+                        UploadProgress {
+                            document_path: document_path.clone(),
+                            local_path: file.to_string_lossy().to_string(),
+                            index,
+                            total,
+                            status,
+                            hosted_url,
+                            error,
+                        },
+                    );
+                };
+                progress(UploadStatus::Started, None, None);
+
+                let outcome =
+                    upload_one(host.as_ref(), &target_key, &cache, &history, file, &options);
+                match &outcome {
+                    Ok((url, reused)) => {
+                        let status = if *reused {
+                            UploadStatus::Reused
+                        } else {
+                            UploadStatus::Uploaded
+                        };
+                        progress(status, Some(url.clone()), None);
+                    }
+                    Err(error) => progress(UploadStatus::Failed, None, Some(error.clone())),
+                }
+                if let Ok(mut results) = results.lock() {
+                    results[index] = Some(outcome);
+                }
+            });
+        }
+    });
+
+    // Persist whatever succeeded, even when some files failed.
+    cache.save()?;
+    if let Ok(history) = history.into_inner() {
+        upload_history::record(app, history)?;
+    }
+
+    let results = results
+        .into_inner()
+        .map_err(|_| "upload results lock poisoned".to_string())?;
+    let mut uploaded = Vec::new();
+    let mut outcomes: HashMap<&Path, Result<&str, &str>> = HashMap::new();
+    for (file, result) in files.iter().zip(&results) {
+        match result {
+            Some(Ok((url, reused))) => {
+                uploaded.push(UploadedImage {
+                    local_path: file.to_string_lossy().to_string(),
+                    hosted_url: url.clone(),
+                    reused: *reused,
+                });
+                outcomes.insert(file, Ok(url));
+            }
+            Some(Err(error)) => {
+                outcomes.insert(file, Err(error));
+            }
+            None => {
+                outcomes.insert(file, Err("upload did not run"));
+            }
+        }
+    }
+
+    let mut failures = Vec::new();
+    let mut out = text;
+    for (reference, local) in references.iter().rev() {
+        match outcomes.get(local.as_path()) {
+            Some(Ok(url)) => out.replace_range(reference.start..reference.end, url),
+            Some(Err(error)) => failures.push(UploadFailure {
+                url: reference.url.clone(),
+                error: error.to_string(),
+            }),
+            None => {}
+        }
+    }
+    failures.reverse();
+
+    Ok(BatchUploadResult {
+        text: out,
+        uploaded,
+        failures,
+    })
+}
+
+/// Parallel uploads allowed for `host`. Each upload to a GitHub, GitLab or
+/// Gitee repository is a commit on the same branch, and concurrent commits
+/// are rejected with a conflict, so those run one at a time.
+fn worker_limit(host: &HostConfig, requested: usize) -> usize {
+    match host {
+        HostConfig::Github(_) | HostConfig::Gitlab(_) | HostConfig::Gitee(_) => 1,
+        HostConfig::S3(_) | HostConfig::Command(_) => requested.clamp(1, MAX_CONCURRENCY),
+    }
+}
+
+fn upload_one(
+    host: &dyn ImageHost,
+    target_key: &str,
+    cache: &UploadCache,
+    history: &Mutex<Vec<HistoryEntry>>,
+    file: &Path,
+    options: &BatchUploadOptions,
+) -> UploadOutcome {
+    if !crate::is_allowed_image_extension(file) {
+        return Err("unsupported image type".to_string());
+    }
+    let original = image_host::read_upload_source(file)?;
+    // Re-runs (or other documents using the same image) skip processing.
+    let hash = assets::content_hash(&original);
+    if let Some(file) = cache.get_by_source(host, &hash) {
+        return Ok((file.url, true));
+    }
+
+    let (bytes, ext) =
+        image_host::prepare_upload_bytes(file, original, options.max_bytes, &options.upload)?;
+    // Parallel uploads share a millisecond timestamp; the hash keeps names apart.
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let stem = format!("{}-{}", stem, &hash[..8]);
+    let (uploaded, reused) = upload_cache::upload_deduplicated(
+        host,
+        cache,
+        &stem,
+        &ext,
+        &bytes,
+        Some(&hash),
+        options.upload.check_remote,
+        &AtomicBool::new(false),
+    )?;
+    if let Ok(mut history) = history.lock() {
+        history.push(HistoryEntry::new(
+            &file.to_string_lossy(),
+            target_key,
+            &uploaded,
+            upload_cache::git_blob_sha(&bytes),
+            options.upload.document_path.as_deref(),
+            reused,
+        ));
+    }
+    Ok((uploaded.url, reused))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(json: &str) -> HostConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn repository_hosts_upload_one_at_a_time() {
+        let github = host(r#"{"kind":"github","repo":"octo/images"}"#);
+        let gitlab = host(r#"{"kind":"gitlab","project":"octo/images"}"#);
+        for repository in [&github, &gitlab] {
+            assert_eq!(worker_limit(repository, DEFAULT_CONCURRENCY), 1);
+            assert_eq!(worker_limit(repository, MAX_CONCURRENCY), 1);
+        }
+
+        let command = host(r#"{"kind":"command","id":"picgo"}"#);
+        assert_eq!(worker_limit(&command, 0), 1);
+        assert_eq!(worker_limit(&command, DEFAULT_CONCURRENCY), 3);
+        assert_eq!(worker_limit(&command, 100), MAX_CONCURRENCY);
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::assets;
+use crate::image_host::{self, HostError, HostValidation, ImageHost, UploadedFile};
+
+const UPLOAD_COMMANDS_FILE: &str = "carbo-assets/upload-commands.json";
+
+/// Host config sent by the webview: only the id of a saved uploader, so a
+/// script in the webview cannot choose what program runs.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommandHostRef {
+    pub id: String,
+}
+
+/// An uploader saved with `write_upload_command`, in
+/// `$APPDATA/carbo-assets/upload-commands.json`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommandHostConfig {
+    /// Executable name (looked up on `PATH`) or path, e.g. `picgo`.
+    pub program: String,
+    /// `{file}` is replaced by the image path. Defaults to `["{file}"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Arguments for removing an upload; `{url}` is replaced by its URL.
+    /// Without them the host does not support deletion.
+    #[serde(default)]
+    pub delete_args: Option<Vec<String>>,
+    /// Overrides the upload timeout; uploaders may need longer than an HTTP request.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+type UploadCommands = BTreeMap<String, CommandHostConfig>;
+
+fn commands_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(UPLOAD_COMMANDS_FILE, BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+fn load_commands(app: &tauri::AppHandle) -> Result<UploadCommands, String> {
+    let path = commands_path(app)?;
+    if !path.is_file() {
+        return Ok(UploadCommands::new());
+    }
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+impl CommandHostRef {
+    /// The saved uploader this refers to.
+    pub(crate) fn resolve(&self, app: &tauri::AppHandle) -> Result<CommandHostConfig, String> {
+        load_commands(app)?
+            .remove(self.id.trim())
+            .ok_or_else(|| format!("no upload command is saved as {}", self.id.trim()))
+    }
+}
+
+/// Runs a user-configured uploader with the image path and takes the last
+/// http(s) URL it prints to stdout.
+pub(crate) struct CommandHost {
+    config: CommandHostConfig,
+    timeout: Duration,
+}
+
+impl CommandHost {
+    pub(crate) fn new(config: &CommandHostConfig, timeout: Duration) -> Result<Self, String> {
+        if config.program.trim().is_empty() {
+            return Err("upload command is not configured".to_string());
+        }
+        let mut config = config.clone();
+        if config.args.is_empty() {
+            config.args = vec!["{file}".to_string()];
+        }
+        let timeout = config
+            .timeout_secs
+            .map_or(timeout, |secs| Duration::from_secs(secs.max(1)));
+        Ok(Self { config, timeout })
+    }
+
+    fn run(&self, args: &[String]) -> Result<String, String> {
+        let program = self.config.program.trim();
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", program, e))?;
+
+        // Drain both pipes while waiting so a chatty uploader cannot block on a full pipe.
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} timed out", program));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            let detail = if stderr.trim().is_empty() {
+                &stdout
+            } else {
+                &stderr
+            };
+            return Err(format!(
+                "{} failed ({}): {}",
+                program,
+                status,
+                detail.trim()
+            ));
+        }
+        Ok(stdout)
+    }
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).to_string()
+    })
+}
+
+// Uploaders log progress before the result, so the last URL wins.
+fn last_url(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .map(|token| {
+            token.trim_matches(|c: char| {
+                matches!(c, '"' | '\'' | '<' | '>' | '(' | ')' | '[' | ']' | ',')
+            })
+        })
+        .rev()
+        .find(|token| crate::markdown_images::is_remote_url(token))
+        .map(str::to_string)
+}
+
+fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let dirs = std::env::var_os("PATH")?;
+    std::env::split_paths(&dirs).find_map(|dir| {
+        let plain = dir.join(program);
+        let with_suffix = dir.join(format!("{}{}", program, std::env::consts::EXE_SUFFIX));
+        // `.cmd` covers npm-installed tools such as PicGo on Windows.
+        let cmd = dir.join(format!("{}.cmd", program));
+        [plain, with_suffix, cmd].into_iter().find(|p| p.is_file())
+    })
+}
+
+impl ImageHost for CommandHost {
+    fn target_key(&self) -> String {
+        format!(
+            "command:{} {}",
+            self.config.program.trim(),
+            self.config.args.join(" ")
+        )
+    }
+
+    fn validate(&self) -> Result<HostValidation, HostError> {
+        let found = find_program(self.config.program.trim()).is_some();
+        Ok(HostValidation {
+            writable: found,
+            message: (!found).then(|| format!("{} was not found", self.config.program.trim())),
+        })
+    }
+
+    fn upload(&self, file_name: &str, bytes: &[u8]) -> Result<UploadedFile, HostError> {
+        // The uploader sees the processed bytes under the intended name.
+        let dir =
+            std::env::temp_dir().join(format!("carbo-upload-{}", assets::content_hash(bytes)));
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let file = dir.join(file_name);
+        let result = std::fs::write(&file, bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                let file = file.to_string_lossy();
+                let args: Vec<String> = self
+                    .config
+                    .args
+                    .iter()
+                    .map(|a| a.replace("{file}", &file))
+                    .collect();
+                self.run(&args)
+            });
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let output = result?;
+        let url = last_url(&output)
+            .ok_or_else(|| format!("no URL in upload command output: {}", output.trim()))?;
+        Ok(UploadedFile {
+            remote_path: url.clone(),
+            url,
+        })
+    }
+
+    fn delete(&self, remote_path: &str) -> Result<(), HostError> {
+        let args = self
+            .config
+            .delete_args
+            .as_ref()
+            .ok_or_else(|| "this upload command does not support deleting".to_string())?;
+        let args: Vec<String> = args
+            .iter()
+            .map(|a| a.replace("{url}", remote_path))
+            .collect();
+        self.run(&args)?;
+        Ok(())
+    }
+
+    fn public_url(&self, remote_path: &str) -> String {
+        remote_path.to_string()
+    }
+}
+
+/// Saved uploaders by id.
+#[tauri::command]
+pub(crate) fn read_upload_commands(app: tauri::AppHandle) -> Result<UploadCommands, String> {
+    load_commands(&app)
+}
+
+/// Saves the uploader `id`, or removes it when `command` is `None`. A new or
+/// changed program or argument list only takes effect once the user allows
+/// it in a native dialog, which the webview cannot answer for them. Returns
+/// whether anything was saved.
+#[tauri::command]
+pub(crate) async fn write_upload_command(
+    app: tauri::AppHandle,
+    id: String,
+    command: Option<CommandHostConfig>,
+) -> Result<bool, String> {
+    image_host::run_blocking(move || {
+        let id = id.trim().to_string();
+        if id.is_empty() {
+            return Err("upload command id is empty".to_string());
+        }
+        let mut commands = load_commands(&app)?;
+        match command {
+            None => {
+                if commands.remove(&id).is_none() {
+                    return Ok(false);
+                }
+            }
+            Some(command) => {
+                if command.program.trim().is_empty() {
+                    return Err("upload command is not configured".to_string());
+                }
+                let unchanged = commands.get(&id).is_some_and(|saved| {
+                    saved.program == command.program
+                        && saved.args == command.args
+                        && saved.delete_args == command.delete_args
+                });
+                if !unchanged && !confirm_command(&app, &command) {
+                    return Ok(false);
+                }
+                commands.insert(id, command);
+            }
+        }
+        let path = commands_path(&app)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_vec_pretty(&commands).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| e.to_string())?;
+        Ok(true)
+    })
+    .await
+}
+
+fn confirm_command(app: &tauri::AppHandle, command: &CommandHostConfig) -> bool {
+    let mut message = format!(
+        "Allow Carbo to run this program to upload images?\n\n{} {}",
+        command.program.trim(),
+        command.args.join(" ")
+    );
+    if let Some(args) = &command.delete_args {
+        message.push_str(&format!(
+            "\n\nTo delete uploads:\n{} {}",
+            command.program.trim(),
+            args.join(" ")
+        ));
+    }
+    app.dialog()
+        .message(message)
+        .title("Upload command")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Cancel".to_string(),
+        ))
+        .blocking_show()
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::image_host::{self, HostConfig, HostError, HostErrorKind};
+
+const KEYRING_SERVICE: &str = "com.carbo.markdown";
+/// Used when the platform secret store is unavailable, e.g. Linux without a
+/// Secret Service daemon. Tokens are encrypted with a key kept next to them,
+/// readable only by the user, which keeps them out of plain-text files but is
+/// no stronger than the file permissions.
+const FALLBACK_FILE: &str = "carbo-assets/credentials.json";
+const FALLBACK_KEY_FILE: &str = "carbo-assets/credentials.key";
+
+fn keyring_entry(key: &str) -> Option<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, key).ok()
+}
+
+struct FallbackStore {
+    path: PathBuf,
+    key_path: PathBuf,
+}
+
+impl FallbackStore {
+    fn open(app: &tauri::AppHandle) -> Result<Self, String> {
+        let resolve = |file| {
+            app.path()
+                .resolve(file, BaseDirectory::AppData)
+                .map_err(|e| e.to_string())
+        };
+        Ok(Self {
+            path: resolve(FALLBACK_FILE)?,
+            key_path: resolve(FALLBACK_KEY_FILE)?,
+        })
+    }
+
+    fn entries(&self) -> HashMap<String, String> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, entries: &HashMap<String, String>) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(entries).map_err(|e| e.to_string())?;
+        write_private(&self.path, &json)
+    }
+
+    fn cipher(&self, create: bool) -> Result<Option<LessSafeKey>, String> {
+        let bytes = match std::fs::read(&self.key_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+                let mut bytes = vec![0u8; CHACHA20_POLY1305.key_len()];
+                SystemRandom::new()
+                    .fill(&mut bytes)
+                    .map_err(|_| "could not generate a key".to_string())?;
+                write_private(&self.key_path, &bytes)?;
+                bytes
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+            .map_err(|_| "credential key file is corrupt".to_string())?;
+        Ok(Some(LessSafeKey::new(key)))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        let Some(sealed) = self.entries().remove(key) else {
+            return Ok(None);
+        };
+        let Some(cipher) = self.cipher(false)? else {
+            return Ok(None);
+        };
+        let mut sealed = base64::engine::general_purpose::STANDARD
+            .decode(sealed)
+            .map_err(|e| e.to_string())?;
+        if sealed.len() < NONCE_LEN {
+            return Err("stored credential is corrupt".to_string());
+        }
+        let mut ciphertext = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed)
+            .map_err(|_| "stored credential is corrupt".to_string())?;
+        let plain = cipher
+            .open_in_place(nonce, Aad::from(key.as_bytes()), &mut ciphertext)
+            .map_err(|_| "stored credential could not be decrypted".to_string())?;
+        String::from_utf8(plain.to_vec())
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    fn set(&self, key: &str, token: &str) -> Result<(), String> {
+        let cipher = self
+            .cipher(true)?
+            .ok_or_else(|| "could not create a credential key".to_string())?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| "could not generate a nonce".to_string())?;
+        let mut sealed = token.as_bytes().to_vec();
+        cipher
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| "could not encrypt the credential".to_string())?;
+        let mut stored = nonce.to_vec();
+        stored.extend_from_slice(&sealed);
+
+        let mut entries = self.entries();
+        entries.insert(
+            key.to_string(),
+            base64::engine::general_purpose::STANDARD.encode(stored),
+        );
+        self.save(&entries)
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, String> {
+        let mut entries = self.entries();
+        if entries.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.save(&entries)?;
+        Ok(true)
+    }
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    file.write_all(bytes).map_err(|e| e.to_string())
+}
+
+pub(crate) fn stored_token(app: &tauri::AppHandle, key: &str) -> Result<Option<String>, String> {
+    if let Some(entry) = keyring_entry(key) {
+        if let Ok(token) = entry.get_password() {
+            return Ok(Some(token));
+        }
+    }
+    FallbackStore::open(app)?.get(key)
+}
+
+fn store_token(app: &tauri::AppHandle, key: &str, token: &str) -> Result<(), String> {
+    let fallback = FallbackStore::open(app)?;
+    let in_keyring = keyring_entry(key).is_some_and(|entry| entry.set_password(token).is_ok());
+    if in_keyring {
+        // Do not leave an older copy behind in the fallback file.
+        fallback.remove(key)?;
+        Ok(())
+    } else {
+        fallback.set(key, token)
+    }
+}
+
+fn remove_token(app: &tauri::AppHandle, key: &str) -> Result<bool, String> {
+    let in_keyring = keyring_entry(key).is_some_and(|entry| entry.delete_credential().is_ok());
+    let in_fallback = FallbackStore::open(app)?.remove(key)?;
+    Ok(in_keyring || in_fallback)
+}
+
+/// Fills an empty `token` with the one stored under `key`.
+pub(crate) fn fill_token(
+    app: &tauri::AppHandle,
+    key: &str,
+    token: &mut String,
+) -> Result<(), HostError> {
+    if !token.trim().is_empty() {
+        return Ok(());
+    }
+    match stored_token(app, key)? {
+        Some(stored) => {
+            *token = stored;
+            Ok(())
+        }
+        None => Err(HostError::new(
+            HostErrorKind::Auth,
+            format!("no token is stored for {}", key),
+        )),
+    }
+}
+
+/// Fills the host's token or secret from the store when the config has none.
+pub(crate) fn fill_host_secret(
+    app: &tauri::AppHandle,
+    host: &mut HostConfig,
+) -> Result<(), HostError> {
+    match host.secret_mut() {
+        Some((key, secret)) => fill_token(app, &key, secret),
+        None => Ok(()),
+    }
+}
+
+fn host_key(mut host: HostConfig) -> Result<String, String> {
+    host.secret_mut()
+        .map(|(key, _)| key)
+        .ok_or_else(|| "this host does not use a stored token".to_string())
+}
+
+/// Stores the token or secret key for `host` (its own token field is
+/// ignored) in the OS keyring. Tokens are shared by hosts on the same server.
+#[tauri::command]
+pub(crate) async fn set_host_token(
+    app: tauri::AppHandle,
+    host: HostConfig,
+    token: String,
+) -> Result<(), String> {
+    if token.trim().is_empty() {
+        return Err("token is empty".to_string());
+    }
+    image_host::run_blocking(move || store_token(&app, &host_key(host)?, token.trim())).await
+}
+
+/// Returns whether a token was stored.
+#[tauri::command]
+pub(crate) async fn clear_host_token(
+    app: tauri::AppHandle,
+    host: HostConfig,
+) -> Result<bool, String> {
+    image_host::run_blocking(move || remove_token(&app, &host_key(host)?)).await
+}
+
+#[tauri::command]
+pub(crate) async fn has_host_token(
+    app: tauri::AppHandle,
+    host: HostConfig,
+) -> Result<bool, String> {
+    image_host::run_blocking(move || Ok(stored_token(&app, &host_key(host)?)?.is_some())).await
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{assets, image_host, image_metadata, image_type, markdown_images};
+
+const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+const MAX_GALLERY_ENTRIES: usize = 5000;
+const MAX_DOCUMENT_BYTES: u64 = 5 * 1024 * 1024;
+const SNIFF_BYTES: u64 = 4096;
+
+/// Which images the gallery lists.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum GalleryScope {
+    /// Images referenced by the document plus its asset folders (see `layout`).
+    #[default]
+    Document,
+    /// Every image file under the workspace.
+    Workspace,
+    /// The `carbo-assets/images` AppData store.
+    AppData,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImageAssetInfo {
+    path: String,
+    name: String,
+    format: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    byte_size: u64,
+    modified_ms: u64,
+    /// Markdown documents that reference this image.
+    referenced_by: Vec<String>,
+    /// Cached PNG thumbnail, absent for formats that cannot be rasterized.
+    thumbnail_path: Option<String>,
+}
+
+/// Lists images with their metadata for the gallery panel. References are
+/// resolved against every Markdown file in `workspace_path` when given,
+/// otherwise against the document alone. `layout` is the asset layout images
+/// are saved with, which locates the document's asset folders.
+#[tauri::command]
+pub(crate) async fn list_image_assets(
+    app: tauri::AppHandle,
+    scope: Option<GalleryScope>,
+    document_path: Option<String>,
+    workspace_path: Option<String>,
+    layout: Option<assets::AssetLayout>,
+    thumbnail_size: Option<u32>,
+) -> Result<Vec<ImageAssetInfo>, String> {
+    image_host::run_blocking(move || {
+        list_image_assets_blocking(
+            &app,
+            scope,
+            document_path,
+            workspace_path,
+            layout,
+            thumbnail_size,
+        )
+    })
+    .await
+}
+
+fn list_image_assets_blocking(
+    app: &tauri::AppHandle,
+    scope: Option<GalleryScope>,
+    document_path: Option<String>,
+    workspace_path: Option<String>,
+    layout: Option<assets::AssetLayout>,
+    thumbnail_size: Option<u32>,
+) -> Result<Vec<ImageAssetInfo>, String> {
+    let document = document_path.map(|p| normalize(Path::new(&p)));
+    let workspace = workspace_path.map(PathBuf::from);
+
+    let mut documents: Vec<PathBuf> = Vec::new();
+    if let Some(workspace) = &workspace {
+        if workspace.is_dir() {
+            for entry in crate::collect_text_entries(workspace)? {
+                documents.push(normalize(Path::new(&entry.path)));
+            }
+        }
+    }
+    if let Some(document) = &document {
+        if !documents.contains(document) {
+            documents.push(document.clone());
+        }
+    }
+    let references = collect_references(&documents);
+
+    let images = match scope.unwrap_or_default() {
+        GalleryScope::Document => {
+            let document = document
+                .as_ref()
+                .ok_or_else(|| "document scope requires a document path".to_string())?;
+            document_images(document, &layout.unwrap_or_default(), &references)?
+        }
+        GalleryScope::Workspace => {
+            let workspace = workspace
+                .as_ref()
+                .filter(|w| w.is_dir())
+                .ok_or_else(|| "workspace scope requires a workspace directory".to_string())?;
+            let mut out = Vec::new();
+            walk_images(workspace, &mut out)?;
+            out
+        }
+        GalleryScope::AppData => {
+            let dir = app
+                .path()
+                .resolve("carbo-assets/images", BaseDirectory::AppData)
+                .map_err(|e| e.to_string())?;
+            let mut out = Vec::new();
+            if dir.is_dir() {
+                for entry in std::fs::read_dir(&dir).map_err(|e| e.to_string())? {
+                    let path = entry.map_err(|e| e.to_string())?.path();
+                    if path.is_file() && crate::is_allowed_image_extension(&path) {
+                        out.push(path);
+                    }
+                }
+            }
+            out
+        }
+    };
+
+    let size = thumbnail_size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    let thumbnail_dir = if size == 0 {
+        None
+    } else {
+        Some(
+            app.path()
+                .resolve("carbo-assets/thumbnails", BaseDirectory::AppData)
+                .map_err(|e| e.to_string())?,
+        )
+    };
+
+    let mut out = Vec::with_capacity(images.len());
+    for path in images.into_iter().take(MAX_GALLERY_ENTRIES) {
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let referenced_by = references
+            .get(&normalize(&path))
+            .cloned()
+            .unwrap_or_default();
+        let (width, height) = image::ImageReader::open(&path)
+            .ok()
+            .and_then(|r| r.with_guessed_format().ok())
+            .and_then(|r| r.into_dimensions().ok())
+            .map_or((None, None), |(w, h)| (Some(w), Some(h)));
+        let thumbnail_path = thumbnail_dir
+            .as_ref()
+            .and_then(|dir| cached_thumbnail(dir, &path, &meta, size))
+            .map(|p| p.to_string_lossy().to_string());
+
+        out.push(ImageAssetInfo {
+            name: path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            format: sniff_format(&path),
+            width,
+            height,
+            byte_size: meta.len(),
+            modified_ms: modified_ms(&meta),
+            referenced_by,
+            thumbnail_path,
+            path: display_path(&path),
+        });
+    }
+    out.sort_by_key(|a| a.name.to_lowercase());
+    Ok(out)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Canonical paths on Windows carry a `\\?\` prefix the webview should not see.
+fn display_path(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    text.strip_prefix(r"\\?\").unwrap_or(&text).to_string()
+}
+
+// Image path -> documents referencing it.
+fn collect_references(documents: &[PathBuf]) -> HashMap<PathBuf, Vec<String>> {
+    let mut map: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    for document in documents {
+        let readable = std::fs::metadata(document).is_ok_and(|m| m.len() <= MAX_DOCUMENT_BYTES);
+        if !readable {
+            continue;
+        }
+        let Ok(text) = std::fs::read_to_string(document) else {
+            continue;
+        };
+        let doc_name = display_path(document);
+        for reference in markdown_images::find_image_references(&text) {
+            let Some(local) = markdown_images::resolve_local_reference(&reference.url, document)
+            else {
+                continue;
+            };
+            let entry = map.entry(normalize(&local)).or_default();
+            if !entry.contains(&doc_name) {
+                entry.push(doc_name.clone());
+            }
+        }
+    }
+    map
+}
+
+fn document_images(
+    document: &Path,
+    layout: &assets::AssetLayout,
+    references: &HashMap<PathBuf, Vec<String>>,
+) -> Result<Vec<PathBuf>, String> {
+    let doc_name = display_path(document);
+    let mut out: Vec<PathBuf> = references
+        .iter()
+        .filter(|(path, docs)| docs.contains(&doc_name) && path.is_file())
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    for assets_dir in assets::existing_asset_folders(layout, document)? {
+        let Ok(entries) = std::fs::read_dir(&assets_dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file()
+                && crate::is_allowed_image_extension(&path)
+                && !out.contains(&normalize(&path))
+            {
+                out.push(normalize(&path));
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Symlinks are skipped: they could loop or lead out of the workspace.
+fn walk_images(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), String> {
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        if out.len() >= MAX_GALLERY_ENTRIES {
+            return Ok(());
+        }
+        let entry = entry.map_err(|e| e.to_string())?;
+        let file_type = entry.file_type().map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| s.starts_with('.') || s == "node_modules");
+        if hidden {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk_images(&path, out)?;
+        } else if file_type.is_file() && crate::is_allowed_image_extension(&path) {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn sniff_format(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    image_type::sniff_image_type(&head)
+        .or_else(|| image_type::ImageType::from_extension(crate::file_extension(path)))
+        .map(|t| t.extension().to_string())
+}
+
+fn modified_ms(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Returns a PNG thumbnail for `path`, regenerating it when the source is newer.
+fn cached_thumbnail(dir: &Path, path: &Path, meta: &std::fs::Metadata, size: u32) -> Option<PathBuf> {
+    let key = assets::content_hash(normalize(path).to_string_lossy().as_bytes());
+    let thumb = dir.join(format!("{}-{}.png", key, size));
+
+    let fresh = std::fs::metadata(&thumb)
+        .ok()
+        .and_then(|t| t.modified().ok())
+        .zip(meta.modified().ok())
+        .is_some_and(|(thumb_time, source_time)| thumb_time >= source_time);
+    if fresh {
+        return Some(thumb);
+    }
+
+    let bytes = std::fs::read(path).ok()?;
+    let (img, _) = image_metadata::decode_oriented(&bytes).ok()?;
+    let small = img.thumbnail(size, size);
+    std::fs::create_dir_all(dir).ok()?;
+    small.save_with_format(&thumb, image::ImageFormat::Png).ok()?;
+    Some(thumb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn workspace_walk_skips_symlinks() {
+        let root = std::env::temp_dir().join(format!("carbo-gallery-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let outside = root.join("outside");
+        let workspace = root.join("workspace");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(workspace.join("images")).unwrap();
+        std::fs::write(outside.join("secret.png"), b"png").unwrap();
+        std::fs::write(workspace.join("images/a.png"), b"png").unwrap();
+        std::os::unix::fs::symlink(&outside, workspace.join("linked")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.png"), workspace.join("b.png")).unwrap();
+        // A loop back to the workspace root.
+        std::os::unix::fs::symlink(&workspace, workspace.join("images/loop")).unwrap();
+
+        let mut found = Vec::new();
+        walk_images(&workspace, &mut found).unwrap();
+        assert_eq!(found, [workspace.join("images/a.png")]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
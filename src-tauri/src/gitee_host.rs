@@ -0,0 +1,229 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::image_host::{self, uri_encode, HostError, HostValidation, ImageHost, UploadedFile};
+use crate::url_templates;
+
+const DEFAULT_BASE_URL: &str = "https://gitee.com";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GiteeHostConfig {
+    /// Instance URL, defaults to `https://gitee.com`.
+    #[serde(default)]
+    pub base_url: String,
+    /// `owner/repo`.
+    pub repo: String,
+    /// Defaults to `master`.
+    #[serde(default)]
+    pub branch: String,
+    /// Defaults to `images`.
+    #[serde(default)]
+    pub path_prefix: String,
+    /// Empty to use the token stored for the instance.
+    #[serde(default)]
+    pub token: String,
+    /// URL returned for uploads, e.g. a CDN or custom domain, with `{owner}`,
+    /// `{repo}`, `{branch}` and `{path}`. Defaults to the raw file URL.
+    #[serde(default)]
+    pub url_template: Option<String>,
+}
+
+impl GiteeHostConfig {
+    fn base_url(&self) -> &str {
+        let base_url = self.base_url.trim().trim_end_matches('/');
+        if base_url.is_empty() {
+            DEFAULT_BASE_URL
+        } else {
+            base_url
+        }
+    }
+
+    /// Key the token is stored under in the OS keyring; one per instance.
+    pub(crate) fn credential_key(&self) -> String {
+        format!("gitee:{}", self.base_url())
+    }
+}
+
+/// Stores images through the Gitee v5 contents API.
+pub(crate) struct GiteeHost {
+    base_url: String,
+    owner: String,
+    name: String,
+    branch: String,
+    prefix: String,
+    token: String,
+    url_template: Option<String>,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Serialize)]
+struct GiteeCreateFileReq<'a> {
+    access_token: &'a str,
+    content: String,
+    message: String,
+    branch: &'a str,
+}
+
+#[derive(Deserialize)]
+struct GiteeCreateFileResp {
+    content: GiteeContentInfo,
+}
+
+#[derive(Deserialize)]
+struct GiteeContentInfo {
+    path: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GiteeRepoResp {
+    permission: Option<GiteeRepoPermission>,
+}
+
+#[derive(Deserialize)]
+struct GiteeRepoPermission {
+    push: Option<bool>,
+}
+
+impl GiteeHost {
+    pub(crate) fn new(
+        config: &GiteeHostConfig,
+        client: reqwest::blocking::Client,
+    ) -> Result<Self, String> {
+        let (owner, name) = image_host::parse_owner_repo(&config.repo)?;
+        let base_url = config.base_url();
+        let branch = config.branch.trim();
+        let branch = if branch.is_empty() { "master" } else { branch };
+        let prefix = config.path_prefix.trim().trim_matches('/');
+        let prefix = if prefix.is_empty() { "images" } else { prefix };
+        Ok(Self {
+            base_url: base_url.to_string(),
+            owner,
+            name,
+            branch: branch.to_string(),
+            prefix: prefix.to_string(),
+            token: config.token.clone(),
+            url_template: config.url_template.clone().filter(|t| !t.trim().is_empty()),
+            client,
+        })
+    }
+
+    fn repo_url(&self) -> String {
+        format!(
+            "{}/api/v5/repos/{}/{}",
+            self.base_url, self.owner, self.name
+        )
+    }
+
+    fn contents_url(&self, path: &str) -> String {
+        format!("{}/contents/{}", self.repo_url(), uri_encode(path, false))
+    }
+}
+
+impl ImageHost for GiteeHost {
+    fn target_key(&self) -> String {
+        format!(
+            "gitee:{}/{}/{}@{}/{}",
+            self.base_url, self.owner, self.name, self.branch, self.prefix
+        )
+    }
+
+    fn validate(&self) -> Result<HostValidation, HostError> {
+        let resp = self
+            .client
+            .get(self.repo_url())
+            .query(&[("access_token", &self.token)])
+            .send()
+            .map_err(|e| HostError::network("gitee validate", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitee validate", resp));
+        }
+
+        let parsed: GiteeRepoResp = resp.json().map_err(|e| e.to_string())?;
+        let writable = parsed.permission.and_then(|p| p.push).unwrap_or(false);
+        Ok(HostValidation {
+            writable,
+            message: (!writable).then(|| "token has no push access to the repository".to_string()),
+        })
+    }
+
+    fn upload(&self, file_name: &str, bytes: &[u8]) -> Result<UploadedFile, HostError> {
+        let dst_path = format!("{}/{}", self.prefix, file_name);
+        let req_body = GiteeCreateFileReq {
+            access_token: &self.token,
+            content: base64::engine::general_purpose::STANDARD.encode(bytes),
+            message: format!("chore(images): add {}", dst_path),
+            branch: &self.branch,
+        };
+
+        let resp = self
+            .client
+            .post(self.contents_url(&dst_path))
+            .json(&req_body)
+            .send()
+            .map_err(|e| HostError::network("gitee upload", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitee upload", resp));
+        }
+
+        let parsed: GiteeCreateFileResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(UploadedFile {
+            url: self.public_url(&parsed.content.path),
+            remote_path: parsed.content.path,
+        })
+    }
+
+    fn delete(&self, remote_path: &str) -> Result<(), HostError> {
+        // Like GitHub, deleting needs the blob sha.
+        let resp = self
+            .client
+            .get(self.contents_url(remote_path))
+            .query(&[("access_token", &self.token), ("ref", &self.branch)])
+            .send()
+            .map_err(|e| HostError::network("gitee lookup", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitee lookup", resp));
+        }
+        let info: GiteeContentInfo = resp.json().map_err(|e| e.to_string())?;
+
+        let message = format!("chore(images): remove {}", info.path);
+        let resp = self
+            .client
+            .delete(self.contents_url(remote_path))
+            .query(&[
+                ("access_token", self.token.as_str()),
+                ("sha", info.sha.as_str()),
+                ("message", message.as_str()),
+                ("branch", self.branch.as_str()),
+            ])
+            .send()
+            .map_err(|e| HostError::network("gitee delete", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitee delete", resp));
+        }
+        Ok(())
+    }
+
+    fn public_url(&self, remote_path: &str) -> String {
+        if let Some(template) = &self.url_template {
+            return url_templates::expand(
+                template,
+                &[
+                    ("owner", &self.owner),
+                    ("repo", &self.name),
+                    ("branch", &uri_encode(&self.branch, false)),
+                    ("path", &uri_encode(remote_path, false)),
+                ],
+            );
+        }
+        format!(
+            "{}/{}/{}/raw/{}/{}",
+            self.base_url,
+            self.owner,
+            self.name,
+            uri_encode(&self.branch, false),
+            uri_encode(remote_path, false)
+        )
+    }
+}
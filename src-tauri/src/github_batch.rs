@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::github_host::{GitHubHost, GitHubHostConfig};
+use crate::image_host::{self, HostError, ImageHost, UploadOptions, UploadedFile};
+use crate::upload_cache::{self, UploadCache};
+use crate::upload_history::{self, HistoryEntry};
+use crate::upload_tasks::{self, UploadRegistry};
+use crate::{assets, credentials, http_client};
+
+const DEFAULT_COMMIT_MESSAGE: &str = "chore(images): add {count} images";
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct GitHubBatchOptions {
+    pub max_bytes: u64,
+    /// Commit message template. `{count}` is replaced with the number of
+    /// files in the commit and `{files}` with their names.
+    pub commit_message: Option<String>,
+    #[serde(flatten)]
+    pub upload: UploadOptions,
+}
+
+impl Default for GitHubBatchOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            commit_message: None,
+            upload: UploadOptions::default(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubBatchUpload {
+    local_path: String,
+    remote_path: String,
+    url: String,
+    /// Identical content was already in the repository.
+    reused: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubBatchFailure {
+    local_path: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubBatchResult {
+    /// `None` when every file was reused or failed, so nothing was committed.
+    commit_sha: Option<String>,
+    uploaded: Vec<GitHubBatchUpload>,
+    failures: Vec<GitHubBatchFailure>,
+}
+
+/// A file that goes into the commit, or reuses an earlier upload with the
+/// same blob sha.
+enum Planned {
+    Commit { index: usize },
+    Reused { file: UploadedFile, sha: String },
+}
+
+/// Uploads `local_paths` to a GitHub repository in a single commit. Files
+/// that cannot be read or processed are reported in `failures` and left out;
+/// if the commit itself fails, the whole command fails. `cancel_image_upload`
+/// with `upload_id` stops the batch until the branch is updated.
+#[tauri::command]
+pub(crate) async fn github_upload_images_batch(
+    app: tauri::AppHandle,
+    registry: tauri::State<'_, UploadRegistry>,
+    mut host: GitHubHostConfig,
+    local_paths: Vec<String>,
+    options: Option<GitHubBatchOptions>,
+    upload_id: Option<String>,
+) -> Result<GitHubBatchResult, HostError> {
+    let options = options.unwrap_or_default();
+    let (id, cancelled) = registry.register(upload_id)?;
+    let result = image_host::run_blocking(move || {
+        credentials::fill_token(&app, &host.credential_key(), &mut host.token)?;
+        upload_batch_blocking(&app, &host, local_paths, &options, &cancelled)
+    })
+    .await;
+    registry.finish(&id);
+    result
+}
+
+fn upload_batch_blocking(
+    app: &tauri::AppHandle,
+    config: &GitHubHostConfig,
+    local_paths: Vec<String>,
+    options: &GitHubBatchOptions,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<GitHubBatchResult, HostError> {
+    let host = GitHubHost::new(
+        config,
+        http_client::client(app, options.upload.timeout_secs)?,
+        cancelled.clone(),
+    )?;
+    let target_key = host.target_key();
+    let cache = UploadCache::open(app)?;
+    let in_repo = if options.upload.check_remote {
+        host.blobs_under_prefix()?
+    } else {
+        HashMap::new()
+    };
+
+    let mut planned = Vec::new();
+    let mut failures = Vec::new();
+    let mut to_commit: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut shas: Vec<String> = Vec::new();
+    for local_path in local_paths {
+        upload_tasks::check_cancelled(cancelled)?;
+        let src = Path::new(&local_path);
+        let prepared = if crate::is_allowed_image_extension(src) {
+            image_host::read_upload_source(src).and_then(|original| {
+                image_host::prepare_upload_bytes(src, original, options.max_bytes, &options.upload)
+            })
+        } else {
+            Err("unsupported image type".to_string())
+        };
+        let (bytes, ext) = match prepared {
+            Ok(prepared) => prepared,
+            Err(error) => {
+                failures.push(GitHubBatchFailure { local_path, error });
+                continue;
+            }
+        };
+
+        let sha = upload_cache::git_blob_sha(&bytes);
+        let known = cache.get(&host, &sha).or_else(|| {
+            in_repo.get(&sha).map(|remote_path| UploadedFile {
+                url: host.public_url(remote_path),
+                remote_path: remote_path.clone(),
+            })
+        });
+        if let Some(file) = known {
+            planned.push((local_path, Planned::Reused { file, sha }));
+            continue;
+        }
+        // The same content twice in one batch is committed once.
+        if let Some(index) = shas.iter().position(|s| *s == sha) {
+            planned.push((local_path, Planned::Commit { index }));
+            continue;
+        }
+
+        // Names share a millisecond timestamp; the hash keeps them apart.
+        let stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+        let stem = format!("{}-{}", stem, &assets::content_hash(&bytes)[..8]);
+        let file_name = match image_host::remote_file_name(&stem, &ext) {
+            Ok(file_name) => file_name,
+            Err(error) => {
+                failures.push(GitHubBatchFailure { local_path, error });
+                continue;
+            }
+        };
+        planned.push((
+            local_path,
+            Planned::Commit {
+                index: to_commit.len(),
+            },
+        ));
+        to_commit.push((file_name, bytes));
+        shas.push(sha);
+    }
+
+    let (commit_sha, committed) = if to_commit.is_empty() {
+        (None, Vec::new())
+    } else {
+        let files = to_commit
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = options
+            .commit_message
+            .as_deref()
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or(DEFAULT_COMMIT_MESSAGE)
+            .replace("{count}", &to_commit.len().to_string())
+            .replace("{files}", &files);
+        let (sha, committed) = host.commit_files(&to_commit, &message)?;
+        (Some(sha), committed)
+    };
+
+    for (sha, file) in shas.iter().zip(&committed) {
+        cache.insert(&target_key, sha.clone(), file, None);
+    }
+    cache.save()?;
+
+    let mut uploaded = Vec::new();
+    let mut history = Vec::new();
+    for (local_path, plan) in planned {
+        let (file, sha, reused) = match plan {
+            Planned::Reused { file, sha } => (file, sha, true),
+            Planned::Commit { index } => (committed[index].clone(), shas[index].clone(), false),
+        };
+        history.push(HistoryEntry::new(
+            &local_path,
+            &target_key,
+            &file,
+            sha,
+            options.upload.document_path.as_deref(),
+            reused,
+        ));
+        uploaded.push(GitHubBatchUpload {
+            local_path,
+            remote_path: file.remote_path,
+            url: file.url,
+            reused,
+        });
+    }
+    upload_history::record(app, history)?;
+
+    Ok(GitHubBatchResult {
+        commit_sha,
+        uploaded,
+        failures,
+    })
+}
@@ -0,0 +1,1030 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::image_host::{
+    self, uri_encode, HostConfig, HostError, HostErrorKind, HostValidation, ImageHost,
+    UploadOptions, UploadedFile,
+};
+use crate::upload_tasks::{self, UploadRegistry};
+use crate::{credentials, http_client, upload_cache, url_templates};
+
+const GITHUB_API_VERSION: &str = "2022-11-28";
+const DEFAULT_API_BASE: &str = "https://api.github.com";
+const DEFAULT_RAW_BASE: &str = "https://raw.githubusercontent.com";
+/// Attempts per request, including the first.
+const MAX_ATTEMPTS: u32 = 4;
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// How often a backoff or rate-limit wait checks for cancellation.
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+/// Longer rate-limit waits are reported to the caller instead of slept through.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+/// GitHub's advice for secondary rate limits that carry no reset time.
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Which GitHub server to talk to. Both default to github.com.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct GitHubServerConfig {
+    /// REST API base. A bare GitHub Enterprise Server URL such as
+    /// `https://ghe.example.com` gets `/api/v3` appended.
+    pub api_base_url: Option<String>,
+    /// Base for raw file URLs (`<base>/<owner>/<repo>/<branch>/<path>`).
+    /// Defaults to `raw.githubusercontent.com` for github.com and to
+    /// `<server>/raw` otherwise.
+    pub raw_base_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubHostConfig {
+    /// `owner/repo`.
+    pub repo: String,
+    /// Defaults to the repository's default branch.
+    #[serde(default)]
+    pub branch: String,
+    /// Defaults to `images`.
+    #[serde(default)]
+    pub path_prefix: String,
+    /// Empty to use the token stored for the server.
+    #[serde(default)]
+    pub token: String,
+    /// URL returned for uploads instead of the raw file URL, with `{owner}`,
+    /// `{repo}`, `{branch}` and `{path}`, e.g.
+    /// `https://cdn.jsdelivr.net/gh/{owner}/{repo}@{branch}/{path}`.
+    #[serde(default)]
+    pub url_template: Option<String>,
+    #[serde(flatten)]
+    pub server: GitHubServerConfig,
+}
+
+impl GitHubHostConfig {
+    /// Key the token is stored under in the OS keyring; one per server.
+    pub(crate) fn credential_key(&self) -> String {
+        let api = server_bases(&self.server).map_or_else(
+            |_| self.server.api_base_url.clone().unwrap_or_default(),
+            |(api, _)| api,
+        );
+        credential_key(&api)
+    }
+}
+
+fn credential_key(api_base: &str) -> String {
+    format!("github:{}", api_base)
+}
+
+/// Key of the token for a raw file URL on github.com or a GitHub Enterprise
+/// Server (`https://<server>/raw/...`), or `None` for other URLs.
+pub(crate) fn raw_url_credential_key(url: &str) -> Option<String> {
+    let (host, path) = url.strip_prefix("https://")?.split_once('/')?;
+    if host.eq_ignore_ascii_case("raw.githubusercontent.com") {
+        return Some(credential_key(DEFAULT_API_BASE));
+    }
+    if !path.starts_with("raw/") {
+        return None;
+    }
+    let server = GitHubServerConfig {
+        api_base_url: Some(format!("https://{}", host)),
+        raw_base_url: None,
+    };
+    server_bases(&server)
+        .ok()
+        .map(|(api, _)| credential_key(&api))
+}
+
+/// Stores images as files committed through the GitHub contents API.
+pub(crate) struct GitHubHost {
+    api_base: String,
+    raw_base: String,
+    owner: String,
+    name: String,
+    branch: String,
+    prefix: String,
+    token: String,
+    url_template: Option<String>,
+    client: reqwest::blocking::Client,
+    /// Quota reported by the most recent response.
+    rate_limit: Mutex<Option<GitHubRateLimit>>,
+    /// Repository metadata, fetched at most once.
+    repo: OnceLock<GitHubRepoResp>,
+    /// Set by `cancel_image_upload`; stops retry waits and Git Data commits.
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Serialize)]
+struct GitHubPutContentReq {
+    message: String,
+    content: String,
+    branch: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubPutContentResp {
+    content: GitHubContentInfo,
+}
+
+#[derive(Deserialize)]
+struct GitHubContentInfo {
+    path: String,
+    sha: String,
+}
+
+#[derive(Serialize)]
+struct GitHubDeleteContentReq {
+    message: String,
+    sha: String,
+    branch: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubTreeResp {
+    tree: Vec<GitHubTreeEntry>,
+}
+
+#[derive(Deserialize)]
+struct GitHubTreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    sha: String,
+}
+
+#[derive(Serialize)]
+struct GitHubCreateBlobReq {
+    content: String,
+    encoding: &'static str,
+}
+
+#[derive(Deserialize)]
+struct GitHubShaResp {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubRefResp {
+    object: GitHubShaResp,
+}
+
+#[derive(Deserialize)]
+struct GitHubCommitResp {
+    tree: GitHubShaResp,
+}
+
+#[derive(Serialize)]
+struct GitHubTreeItem {
+    path: String,
+    mode: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    sha: String,
+}
+
+#[derive(Serialize)]
+struct GitHubCreateTreeReq<'a> {
+    base_tree: &'a str,
+    tree: &'a [GitHubTreeItem],
+}
+
+#[derive(Serialize)]
+struct GitHubCreateCommitReq<'a> {
+    message: &'a str,
+    tree: &'a str,
+    parents: [&'a str; 1],
+}
+
+#[derive(Serialize)]
+struct GitHubUpdateRefReq<'a> {
+    sha: &'a str,
+    force: bool,
+}
+
+#[derive(Deserialize)]
+struct GitHubRepoResp {
+    default_branch: String,
+    #[serde(default)]
+    private: bool,
+    permissions: Option<GitHubRepoPermissions>,
+}
+
+#[derive(Deserialize)]
+struct GitHubBranchResp {
+    #[serde(default)]
+    protected: bool,
+}
+
+#[derive(Deserialize)]
+struct GitHubRepoPermissions {
+    push: Option<bool>,
+    admin: Option<bool>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubValidateRepoResult {
+    push: bool,
+    admin: bool,
+    /// Raw URLs into a private repository only work for people with access.
+    private: bool,
+    default_branch: String,
+    /// Branch uploads go to: the configured one, or the default.
+    branch: String,
+    branch_exists: bool,
+    branch_protected: bool,
+    /// Whether files can be committed under the path prefix, as far as can
+    /// be told without writing anything.
+    path_writable: bool,
+    /// Why the path is not writable, when it is not.
+    message: Option<String>,
+    rate_limit: Option<GitHubRateLimit>,
+}
+
+/// REST API quota, from the `X-RateLimit-*` headers.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitHubRateLimit {
+    limit: u64,
+    remaining: u64,
+    /// Unix time in seconds when the quota resets.
+    #[serde(alias = "reset")]
+    reset_at: u64,
+}
+
+#[derive(Deserialize)]
+struct GitHubRateLimitResp {
+    resources: GitHubRateLimitResources,
+}
+
+#[derive(Deserialize)]
+struct GitHubRateLimitResources {
+    core: GitHubRateLimit,
+}
+
+/// Resolves the API and raw bases for `server`.
+fn server_bases(server: &GitHubServerConfig) -> Result<(String, String), String> {
+    let api = server
+        .api_base_url
+        .as_deref()
+        .map(|u| u.trim().trim_end_matches('/'))
+        .filter(|u| !u.is_empty())
+        .unwrap_or(DEFAULT_API_BASE);
+    let (scheme, rest) = api
+        .split_once("://")
+        .filter(|(scheme, _)| matches!(scheme.to_ascii_lowercase().as_str(), "http" | "https"))
+        .ok_or_else(|| "GitHub API URL must start with http:// or https://".to_string())?;
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let hostname = authority.rsplit_once(':').map_or(authority, |(h, _)| h);
+    let origin = format!("{}://{}", scheme, authority);
+
+    // `api.` hosts (github.com) and local mocks serve the API at the root;
+    // GitHub Enterprise Server serves it under `/api/v3`.
+    let hostname = hostname.to_ascii_lowercase();
+    let api = if hostname == "github.com" {
+        DEFAULT_API_BASE.to_string()
+    } else if path.is_empty()
+        && !hostname.starts_with("api.")
+        && !matches!(hostname.as_str(), "localhost" | "127.0.0.1")
+    {
+        format!("{}/api/v3", origin)
+    } else {
+        api.to_string()
+    };
+
+    let raw = match server
+        .raw_base_url
+        .as_deref()
+        .map(|u| u.trim().trim_end_matches('/'))
+        .filter(|u| !u.is_empty())
+    {
+        Some(raw) => raw.to_string(),
+        None if api == DEFAULT_API_BASE => DEFAULT_RAW_BASE.to_string(),
+        None => format!("{}/raw", origin),
+    };
+    Ok((api, raw))
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn rate_limit_from_headers(headers: &HeaderMap) -> Option<GitHubRateLimit> {
+    Some(GitHubRateLimit {
+        limit: header_u64(headers, "x-ratelimit-limit")?,
+        remaining: header_u64(headers, "x-ratelimit-remaining")?,
+        reset_at: header_u64(headers, "x-ratelimit-reset")?,
+    })
+}
+
+/// How long to wait before retrying a rate-limited response, or `None` when
+/// the response is not a rate limit (a plain 403 is a permission problem).
+fn rate_limit_wait(status: StatusCode, headers: &HeaderMap, body: &str) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    if let Some(secs) = header_u64(headers, "retry-after") {
+        return Some(Duration::from_secs(secs));
+    }
+    if header_u64(headers, "x-ratelimit-remaining") == Some(0) {
+        if let Some(reset) = header_u64(headers, "x-ratelimit-reset") {
+            return Some(Duration::from_secs(reset.saturating_sub(unix_now()).max(1)));
+        }
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS || body.to_ascii_lowercase().contains("rate limit") {
+        return Some(SECONDARY_RATE_LIMIT_WAIT);
+    }
+    None
+}
+
+/// Statuses GitHub returns for transient failures.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 500 | 502 | 503 | 504)
+}
+
+impl GitHubHost {
+    /// Without a configured branch, this asks GitHub for the default one.
+    pub(crate) fn new(
+        config: &GitHubHostConfig,
+        client: reqwest::blocking::Client,
+        cancelled: Arc<AtomicBool>,
+    ) -> Result<Self, HostError> {
+        let (owner, name) = image_host::parse_owner_repo(&config.repo)?;
+        let (api_base, raw_base) = server_bases(&config.server)?;
+        let prefix = config.path_prefix.trim().trim_matches('/');
+        let prefix = if prefix.is_empty() { "images" } else { prefix };
+        let mut host = Self {
+            api_base,
+            raw_base,
+            owner,
+            name,
+            branch: config.branch.trim().to_string(),
+            prefix: prefix.to_string(),
+            token: config.token.clone(),
+            url_template: config.url_template.clone().filter(|t| !t.trim().is_empty()),
+            client,
+            rate_limit: Mutex::new(None),
+            repo: OnceLock::new(),
+            cancelled,
+        };
+        if host.branch.is_empty() {
+            let default_branch = host.repo_info()?.default_branch.clone();
+            host.branch = default_branch;
+        }
+        Ok(host)
+    }
+
+    fn contents_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/contents/{}",
+            self.api_base, self.owner, self.name, path
+        )
+    }
+
+    /// Sends an authenticated API request with exponential backoff, waiting
+    /// out short rate limits. GET and HEAD are retried on network errors and
+    /// transient 5xx responses; other methods only when the connection could
+    /// not be made, since a failed write may still have been applied.
+    /// Waits end early with a `Cancelled` error once the host's cancel flag
+    /// is set. `request` is called once per attempt.
+    fn send(
+        &self,
+        context: &str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, HostError> {
+        let mut attempt = 1;
+        loop {
+            let request = request()
+                .bearer_auth(&self.token)
+                .header("X-GitHub-Api-Version", GITHUB_API_VERSION)
+                .build()
+                .map_err(|e| HostError::network(context, e))?;
+            let idempotent = matches!(*request.method(), Method::GET | Method::HEAD);
+            let (error, wait) = match self.client.execute(request) {
+                Err(e) => {
+                    let retryable =
+                        e.is_connect() || (idempotent && (e.is_timeout() || e.is_request()));
+                    let backoff = BACKOFF_BASE * 2u32.pow(attempt - 1);
+                    (HostError::network(context, e), retryable.then_some(backoff))
+                }
+                Ok(resp) => {
+                    if let Some(limit) = rate_limit_from_headers(resp.headers()) {
+                        if let Ok(mut current) = self.rate_limit.lock() {
+                            *current = Some(limit);
+                        }
+                    }
+                    let status = resp.status();
+                    if status.is_success() {
+                        return Ok(resp);
+                    }
+                    let headers = resp.headers().clone();
+                    let body = resp.text().unwrap_or_default();
+                    let mut error = HostError::http(context, status, &body);
+                    if let Some(wait) = rate_limit_wait(status, &headers, &body) {
+                        error.kind = HostErrorKind::RateLimited;
+                        error.retry_after_secs = Some(wait.as_secs().max(1));
+                        (error, Some(wait).filter(|w| *w <= MAX_RATE_LIMIT_WAIT))
+                    } else if idempotent && is_transient(status) {
+                        (error, Some(BACKOFF_BASE * 2u32.pow(attempt - 1)))
+                    } else {
+                        (error, None)
+                    }
+                }
+            };
+            match wait {
+                Some(wait) if attempt < MAX_ATTEMPTS => {
+                    self.sleep(wait)?;
+                    attempt += 1;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// Sleeps for `wait` in short slices, failing as soon as the upload is
+    /// cancelled.
+    fn sleep(&self, wait: Duration) -> Result<(), HostError> {
+        let deadline = Instant::now() + wait;
+        loop {
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            std::thread::sleep(left.min(CANCEL_POLL));
+        }
+    }
+
+    fn rate_limit(&self) -> Option<GitHubRateLimit> {
+        self.rate_limit.lock().ok().and_then(|limit| *limit)
+    }
+
+    fn lookup(&self, path: &str) -> Result<GitHubContentInfo, HostError> {
+        let resp = self.send("github lookup", || {
+            self.client
+                .get(self.contents_url(path))
+                .query(&[("ref", &self.branch)])
+        })?;
+        Ok(resp.json().map_err(|e| e.to_string())?)
+    }
+
+    fn repo_url(&self) -> String {
+        format!("{}/repos/{}/{}", self.api_base, self.owner, self.name)
+    }
+
+    /// Remote paths of the files under the prefix, by blob sha.
+    pub(crate) fn blobs_under_prefix(&self) -> Result<HashMap<String, String>, HostError> {
+        // `<branch>:<prefix>` names the prefix's tree, so only images are listed.
+        let tree = format!("{}:{}", self.branch, self.prefix);
+        let url = format!(
+            "{}/git/trees/{}",
+            self.repo_url(),
+            image_host::uri_encode(&tree, true)
+        );
+        let resp = match self.send("github tree lookup", || {
+            self.client.get(&url).query(&[("recursive", "1")])
+        }) {
+            Ok(resp) => resp,
+            // Empty repository, missing branch or no prefix folder yet.
+            Err(error) if error.kind == HostErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(error) => return Err(error),
+        };
+        let parsed: GitHubTreeResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(parsed
+            .tree
+            .into_iter()
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| (entry.sha, format!("{}/{}", self.prefix, entry.path)))
+            .collect())
+    }
+
+    /// Adds `files` (name, bytes) under the prefix in a single commit through
+    /// the Git Data API and fast-forwards the branch to it. If the branch
+    /// moves meanwhile, the commit is rebuilt on the new head. Cancellation is
+    /// checked before every step up to the branch update. Returns the commit
+    /// sha and the files in order.
+    pub(crate) fn commit_files(
+        &self,
+        files: &[(String, Vec<u8>)],
+        message: &str,
+    ) -> Result<(String, Vec<UploadedFile>), HostError> {
+        let repo_url = self.repo_url();
+        let mut items = Vec::with_capacity(files.len());
+        for (name, bytes) in files {
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            let req_body = GitHubCreateBlobReq {
+                content: base64::engine::general_purpose::STANDARD.encode(bytes),
+                encoding: "base64",
+            };
+            let blob: GitHubShaResp = self
+                .send("github blob", || {
+                    self.client
+                        .post(format!("{}/git/blobs", repo_url))
+                        .json(&req_body)
+                })?
+                .json()
+                .map_err(|e| e.to_string())?;
+            items.push(GitHubTreeItem {
+                path: format!("{}/{}", self.prefix, name),
+                mode: "100644",
+                kind: "blob",
+                sha: blob.sha,
+            });
+        }
+
+        let ref_url = format!("{}/git/refs/heads/{}", repo_url, self.branch);
+        let mut attempt = 1;
+        let commit_sha = loop {
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            let head: GitHubRefResp = self
+                .send("github branch lookup", || {
+                    self.client
+                        .get(format!("{}/git/ref/heads/{}", repo_url, self.branch))
+                })?
+                .json()
+                .map_err(|e| e.to_string())?;
+            let parent: GitHubCommitResp = self
+                .send("github commit lookup", || {
+                    self.client
+                        .get(format!("{}/git/commits/{}", repo_url, head.object.sha))
+                })?
+                .json()
+                .map_err(|e| e.to_string())?;
+
+            let tree_req = GitHubCreateTreeReq {
+                base_tree: &parent.tree.sha,
+                tree: &items,
+            };
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            let tree: GitHubShaResp = self
+                .send("github tree", || {
+                    self.client
+                        .post(format!("{}/git/trees", repo_url))
+                        .json(&tree_req)
+                })?
+                .json()
+                .map_err(|e| e.to_string())?;
+            let commit_req = GitHubCreateCommitReq {
+                message,
+                tree: &tree.sha,
+                parents: [&head.object.sha],
+            };
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            let commit: GitHubShaResp = self
+                .send("github commit", || {
+                    self.client
+                        .post(format!("{}/git/commits", repo_url))
+                        .json(&commit_req)
+                })?
+                .json()
+                .map_err(|e| e.to_string())?;
+
+            let update_req = GitHubUpdateRefReq {
+                sha: &commit.sha,
+                force: false,
+            };
+            upload_tasks::check_cancelled(&self.cancelled)?;
+            match self.send("github branch update", || {
+                self.client.patch(&ref_url).json(&update_req)
+            }) {
+                Ok(_) => break commit.sha,
+                // Not a fast-forward: someone pushed since the head was read.
+                Err(error) if error.status == Some(422) && attempt < MAX_ATTEMPTS => {
+                    attempt += 1;
+                }
+                Err(mut error) => {
+                    if error.status == Some(422) {
+                        error.kind = HostErrorKind::Conflict;
+                    }
+                    return Err(error);
+                }
+            }
+        };
+
+        let uploaded = items
+            .into_iter()
+            .map(|item| UploadedFile {
+                url: self.public_url(&item.path),
+                remote_path: item.path,
+            })
+            .collect();
+        Ok((commit_sha, uploaded))
+    }
+
+    fn repo_info(&self) -> Result<&GitHubRepoResp, HostError> {
+        if let Some(repo) = self.repo.get() {
+            return Ok(repo);
+        }
+        let url = self.repo_url();
+        let resp = self.send("github validate", || self.client.get(&url))?;
+        let parsed: GitHubRepoResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(self.repo.get_or_init(|| parsed))
+    }
+
+    /// Checks permissions, the branch and the path prefix without writing.
+    fn validate_setup(&self) -> Result<GitHubValidateRepoResult, HostError> {
+        let repo = self.repo_info()?;
+        let push = repo
+            .permissions
+            .as_ref()
+            .and_then(|p| p.push)
+            .unwrap_or(false);
+        let admin = repo
+            .permissions
+            .as_ref()
+            .and_then(|p| p.admin)
+            .unwrap_or(false);
+
+        let branch_url = format!("{}/branches/{}", self.repo_url(), self.branch);
+        let branch = match self.send("github branch lookup", || self.client.get(&branch_url)) {
+            Ok(resp) => Some(resp.json::<GitHubBranchResp>().map_err(|e| e.to_string())?),
+            Err(error) if error.kind == HostErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+
+        // Directories list as arrays; a file where the prefix folder should
+        // be would make every upload fail.
+        let prefix_is_file = branch.is_some()
+            && match self.send("github path lookup", || {
+                self.client
+                    .get(self.contents_url(&self.prefix))
+                    .query(&[("ref", &self.branch)])
+            }) {
+                Ok(resp) => resp
+                    .json::<serde_json::Value>()
+                    .map_err(|e| e.to_string())?
+                    .is_object(),
+                Err(error) if error.kind == HostErrorKind::NotFound => false,
+                Err(error) => return Err(error),
+            };
+
+        let message = if !push {
+            Some("token has no push access to the repository".to_string())
+        } else if branch.is_none() {
+            Some(format!("branch {} does not exist", self.branch))
+        } else if prefix_is_file {
+            Some(format!("{} is a file, not a folder", self.prefix))
+        } else {
+            None
+        };
+        Ok(GitHubValidateRepoResult {
+            push,
+            admin,
+            private: repo.private,
+            default_branch: repo.default_branch.clone(),
+            branch: self.branch.clone(),
+            branch_exists: branch.is_some(),
+            branch_protected: branch.as_ref().is_some_and(|b| b.protected),
+            path_writable: message.is_none(),
+            message,
+            rate_limit: self.rate_limit(),
+        })
+    }
+}
+
+impl ImageHost for GitHubHost {
+    fn target_key(&self) -> String {
+        // github.com keys stay short so existing upload maps keep matching.
+        let server = if self.api_base == DEFAULT_API_BASE {
+            String::new()
+        } else {
+            format!("{}/", self.api_base)
+        };
+        format!(
+            "github:{}{}/{}@{}/{}",
+            server, self.owner, self.name, self.branch, self.prefix
+        )
+    }
+
+    fn validate(&self) -> Result<HostValidation, HostError> {
+        let result = self.validate_setup()?;
+        Ok(HostValidation {
+            writable: result.path_writable,
+            message: result.message,
+        })
+    }
+
+    fn upload(&self, file_name: &str, bytes: &[u8]) -> Result<UploadedFile, HostError> {
+        let dst_path = format!("{}/{}", self.prefix, file_name);
+        let req_body = GitHubPutContentReq {
+            message: format!("chore(images): add {}", dst_path),
+            content: base64::engine::general_purpose::STANDARD.encode(bytes),
+            branch: self.branch.clone(),
+        };
+
+        let resp = match self.send("github upload", || {
+            self.client
+                .put(self.contents_url(&dst_path))
+                .json(&req_body)
+        }) {
+            Ok(resp) => resp,
+            // A retried PUT whose earlier attempt landed despite the error
+            // reports the file as existing; it is ours if the content matches.
+            Err(error) if error.status == Some(422) => {
+                let info = match self.lookup(&dst_path) {
+                    Ok(info) if info.sha == upload_cache::git_blob_sha(bytes) => info,
+                    _ => return Err(error),
+                };
+                return Ok(UploadedFile {
+                    url: self.public_url(&info.path),
+                    remote_path: info.path,
+                });
+            }
+            Err(error) => return Err(error),
+        };
+
+        let parsed: GitHubPutContentResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(UploadedFile {
+            url: self.public_url(&parsed.content.path),
+            remote_path: parsed.content.path,
+        })
+    }
+
+    fn delete(&self, remote_path: &str) -> Result<(), HostError> {
+        // The contents API needs the blob sha of the file being deleted.
+        let info = self.lookup(remote_path)?;
+        self.delete_blob(remote_path, &info.sha)
+    }
+
+    fn delete_blob(&self, remote_path: &str, sha: &str) -> Result<(), HostError> {
+        let req_body = GitHubDeleteContentReq {
+            message: format!("chore(images): remove {}", remote_path),
+            sha: sha.to_string(),
+            branch: self.branch.clone(),
+        };
+        self.send("github delete", || {
+            self.client
+                .delete(self.contents_url(remote_path))
+                .json(&req_body)
+        })?;
+        Ok(())
+    }
+
+    fn find_by_blob_sha(&self, sha: &str) -> Result<Option<UploadedFile>, HostError> {
+        Ok(self
+            .blobs_under_prefix()?
+            .remove(sha)
+            .map(|remote_path| UploadedFile {
+                url: self.public_url(&remote_path),
+                remote_path,
+            }))
+    }
+
+    fn public_url(&self, remote_path: &str) -> String {
+        match &self.url_template {
+            Some(template) => url_templates::expand(
+                template,
+                &[
+                    ("owner", &self.owner),
+                    ("repo", &self.name),
+                    ("branch", &uri_encode(&self.branch, false)),
+                    ("path", &uri_encode(remote_path, false)),
+                ],
+            ),
+            None => format!(
+                "{}/{}/{}/{}/{}",
+                self.raw_base,
+                self.owner,
+                self.name,
+                uri_encode(&self.branch, false),
+                uri_encode(remote_path, false)
+            ),
+        }
+    }
+}
+
+#[tauri::command]
+pub(crate) async fn github_validate_repo(
+    app: tauri::AppHandle,
+    repo: String,
+    token: Option<String>,
+    server: Option<GitHubServerConfig>,
+    branch: Option<String>,
+    path_prefix: Option<String>,
+) -> Result<GitHubValidateRepoResult, HostError> {
+    let mut config = GitHubHostConfig {
+        repo,
+        branch: branch.unwrap_or_default(),
+        path_prefix: path_prefix.unwrap_or_default(),
+        token: token.unwrap_or_default(),
+        url_template: None,
+        server: server.unwrap_or_default(),
+    };
+    image_host::run_blocking(move || {
+        credentials::fill_token(&app, &config.credential_key(), &mut config.token)?;
+        GitHubHost::new(&config, http_client::client(&app, None)?, Arc::default())?.validate_setup()
+    })
+    .await
+}
+
+/// Current REST API quota for `token`, or the stored token when it is
+/// omitted. Querying it does not count against it.
+#[tauri::command]
+pub(crate) async fn github_rate_limit(
+    app: tauri::AppHandle,
+    token: Option<String>,
+    server: Option<GitHubServerConfig>,
+) -> Result<GitHubRateLimit, HostError> {
+    image_host::run_blocking(move || {
+        let (api_base, _) = server_bases(&server.unwrap_or_default())?;
+        let mut token = token.unwrap_or_default();
+        credentials::fill_token(&app, &credential_key(&api_base), &mut token)?;
+        let client = http_client::client(&app, None)?;
+        let resp = client
+            .get(format!("{}/rate_limit", api_base))
+            .bearer_auth(&token)
+            .header("X-GitHub-Api-Version", GITHUB_API_VERSION)
+            .send()
+            .map_err(|e| HostError::network("github rate limit", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("github rate limit", resp));
+        }
+        let parsed: GitHubRateLimitResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(parsed.resources.core)
+    })
+    .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn github_upload_image_from_path(
+    app: tauri::AppHandle,
+    registry: tauri::State<'_, UploadRegistry>,
+    repo: String,
+    branch: String,
+    path_prefix: String,
+    token: Option<String>,
+    local_path: String,
+    max_bytes: u64,
+    options: Option<UploadOptions>,
+    server: Option<GitHubServerConfig>,
+    upload_id: Option<String>,
+    url_template: Option<String>,
+) -> Result<String, HostError> {
+    let host = HostConfig::Github(GitHubHostConfig {
+        repo,
+        branch,
+        path_prefix,
+        token: token.unwrap_or_default(),
+        url_template,
+        server: server.unwrap_or_default(),
+    });
+    let uploaded = upload_tasks::run_upload(
+        app,
+        &registry,
+        upload_id,
+        host,
+        local_path,
+        max_bytes,
+        options.unwrap_or_default(),
+    )
+    .await?;
+    Ok(uploaded.url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// API base, raw base, and the expected bases.
+    type Case<'a> = (Option<&'a str>, Option<&'a str>, (&'a str, &'a str));
+
+    fn bases(api: Option<&str>, raw: Option<&str>) -> Result<(String, String), String> {
+        server_bases(&GitHubServerConfig {
+            api_base_url: api.map(str::to_string),
+            raw_base_url: raw.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn derives_api_and_raw_bases() {
+        let github = (DEFAULT_API_BASE, DEFAULT_RAW_BASE);
+        let ghe = (
+            "https://ghe.example.com/api/v3",
+            "https://ghe.example.com/raw",
+        );
+        let cases: &[Case] = &[
+            (None, None, github),
+            (Some(""), Some("  "), github),
+            (Some("https://github.com"), None, github),
+            (Some("HTTPS://GitHub.com/"), None, github),
+            (Some("https://api.github.com/"), None, github),
+            (Some("https://ghe.example.com"), None, ghe),
+            (Some("https://ghe.example.com/"), None, ghe),
+            (Some("  https://ghe.example.com//  "), None, ghe),
+            (Some("https://ghe.example.com/api/v3"), None, ghe),
+            (Some("https://ghe.example.com/api/v3/"), None, ghe),
+            (
+                Some("https://ghe.example.com:8443"),
+                None,
+                (
+                    "https://ghe.example.com:8443/api/v3",
+                    "https://ghe.example.com:8443/raw",
+                ),
+            ),
+            (
+                Some("http://localhost:3000/"),
+                None,
+                ("http://localhost:3000", "http://localhost:3000/raw"),
+            ),
+            (
+                Some("http://127.0.0.1:9000"),
+                None,
+                ("http://127.0.0.1:9000", "http://127.0.0.1:9000/raw"),
+            ),
+            (
+                Some("https://ghe.example.com"),
+                Some("https://raw.ghe.example.com/"),
+                (
+                    "https://ghe.example.com/api/v3",
+                    "https://raw.ghe.example.com",
+                ),
+            ),
+            (
+                None,
+                Some("https://cdn.example.com/github/"),
+                (DEFAULT_API_BASE, "https://cdn.example.com/github"),
+            ),
+        ];
+        for (api, raw, (expected_api, expected_raw)) in cases {
+            assert_eq!(
+                bases(*api, *raw).unwrap(),
+                (expected_api.to_string(), expected_raw.to_string()),
+                "api {:?}, raw {:?}",
+                api,
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_api_urls_without_an_http_scheme() {
+        for api in [
+            "ghe.example.com",
+            "ftp://ghe.example.com",
+            "://ghe.example.com",
+        ] {
+            assert!(bases(Some(api), None).is_err(), "{}", api);
+        }
+    }
+
+    #[test]
+    fn cancelling_ends_a_rate_limit_wait() {
+        use std::io::{Read, Write};
+
+        // Every request is rate limited for longer than the test may take.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 50\r\n\
+                      Content-Length: 0\r\nConnection: close\r\n\r\n",
+                );
+            }
+        });
+
+        let config = GitHubHostConfig {
+            repo: "octo/images".to_string(),
+            branch: "main".to_string(),
+            path_prefix: String::new(),
+            token: "t".to_string(),
+            url_template: None,
+            server: GitHubServerConfig {
+                api_base_url: Some(base),
+                raw_base_url: None,
+            },
+        };
+        let client = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let host = GitHubHost::new(&config, client, cancelled.clone()).unwrap();
+
+        let started = Instant::now();
+        let flag = cancelled.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+        let Err(error) = host.lookup("images/a.png") else {
+            panic!("a rate-limited lookup succeeded");
+        };
+        assert_eq!(error.kind, HostErrorKind::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
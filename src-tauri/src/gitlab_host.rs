@@ -0,0 +1,255 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::image_host::{uri_encode, HostError, HostValidation, ImageHost, UploadedFile};
+use crate::url_templates;
+
+const DEFAULT_BASE_URL: &str = "https://gitlab.com";
+/// Developer, the lowest role that may push.
+const DEVELOPER_ACCESS: u32 = 30;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GitLabHostConfig {
+    /// Instance URL, defaults to `https://gitlab.com`.
+    #[serde(default)]
+    pub base_url: String,
+    /// Project path with namespace (`group/subgroup/project`) or numeric id.
+    pub project: String,
+    /// Defaults to `main`.
+    #[serde(default)]
+    pub branch: String,
+    /// Defaults to `images`.
+    #[serde(default)]
+    pub path_prefix: String,
+    /// Empty to use the token stored for the instance.
+    #[serde(default)]
+    pub token: String,
+    /// URL returned for uploads, e.g. a CDN or GitLab Pages domain, with
+    /// `{project}` (the full path), `{owner}` (its namespace), `{repo}`,
+    /// `{branch}` and `{path}`. Defaults to the raw file URL.
+    #[serde(default)]
+    pub url_template: Option<String>,
+}
+
+impl GitLabHostConfig {
+    fn base_url(&self) -> &str {
+        let base_url = self.base_url.trim().trim_end_matches('/');
+        if base_url.is_empty() {
+            DEFAULT_BASE_URL
+        } else {
+            base_url
+        }
+    }
+
+    /// Key the token is stored under in the OS keyring; one per instance.
+    pub(crate) fn credential_key(&self) -> String {
+        format!("gitlab:{}", self.base_url())
+    }
+}
+
+/// Stores images through the GitLab repository files API.
+pub(crate) struct GitLabHost {
+    base_url: String,
+    project: String,
+    branch: String,
+    prefix: String,
+    token: String,
+    url_template: Option<String>,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Serialize)]
+struct GitLabCreateFileReq {
+    branch: String,
+    content: String,
+    encoding: &'static str,
+    commit_message: String,
+}
+
+#[derive(Serialize)]
+struct GitLabDeleteFileReq {
+    branch: String,
+    commit_message: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabCreateFileResp {
+    file_path: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabProjectResp {
+    path_with_namespace: String,
+    permissions: Option<GitLabPermissions>,
+}
+
+#[derive(Deserialize)]
+struct GitLabPermissions {
+    project_access: Option<GitLabAccess>,
+    group_access: Option<GitLabAccess>,
+}
+
+#[derive(Deserialize)]
+struct GitLabAccess {
+    access_level: u32,
+}
+
+impl GitLabHost {
+    pub(crate) fn new(
+        config: &GitLabHostConfig,
+        client: reqwest::blocking::Client,
+    ) -> Result<Self, String> {
+        let project = config.project.trim().trim_matches('/');
+        if project.is_empty() {
+            return Err("project is required".to_string());
+        }
+        let base_url = config.base_url();
+        let branch = config.branch.trim();
+        let branch = if branch.is_empty() { "main" } else { branch };
+        let prefix = config.path_prefix.trim().trim_matches('/');
+        let prefix = if prefix.is_empty() { "images" } else { prefix };
+        Ok(Self {
+            base_url: base_url.to_string(),
+            project: project.to_string(),
+            branch: branch.to_string(),
+            prefix: prefix.to_string(),
+            token: config.token.clone(),
+            url_template: config.url_template.clone().filter(|t| !t.trim().is_empty()),
+            client,
+        })
+    }
+
+    fn project_url(&self) -> String {
+        format!(
+            "{}/api/v4/projects/{}",
+            self.base_url,
+            uri_encode(&self.project, true)
+        )
+    }
+
+    fn file_url(&self, path: &str) -> String {
+        format!(
+            "{}/repository/files/{}",
+            self.project_url(),
+            uri_encode(path, true)
+        )
+    }
+}
+
+impl ImageHost for GitLabHost {
+    fn target_key(&self) -> String {
+        format!(
+            "gitlab:{}/{}@{}/{}",
+            self.base_url, self.project, self.branch, self.prefix
+        )
+    }
+
+    fn validate(&self) -> Result<HostValidation, HostError> {
+        let resp = self
+            .client
+            .get(self.project_url())
+            .header("PRIVATE-TOKEN", &self.token)
+            .send()
+            .map_err(|e| HostError::network("gitlab validate", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitlab validate", resp));
+        }
+
+        let parsed: GitLabProjectResp = resp.json().map_err(|e| e.to_string())?;
+        let level = parsed
+            .permissions
+            .map(|p| {
+                let project = p.project_access.map_or(0, |a| a.access_level);
+                let group = p.group_access.map_or(0, |a| a.access_level);
+                project.max(group)
+            })
+            .unwrap_or(0);
+        let writable = level >= DEVELOPER_ACCESS;
+        Ok(HostValidation {
+            writable,
+            message: (!writable).then(|| {
+                format!(
+                    "token has no developer access to {}",
+                    parsed.path_with_namespace
+                )
+            }),
+        })
+    }
+
+    fn upload(&self, file_name: &str, bytes: &[u8]) -> Result<UploadedFile, HostError> {
+        let dst_path = format!("{}/{}", self.prefix, file_name);
+        let req_body = GitLabCreateFileReq {
+            branch: self.branch.clone(),
+            content: base64::engine::general_purpose::STANDARD.encode(bytes),
+            encoding: "base64",
+            commit_message: format!("chore(images): add {}", dst_path),
+        };
+
+        let resp = self
+            .client
+            .post(self.file_url(&dst_path))
+            .header("PRIVATE-TOKEN", &self.token)
+            .json(&req_body)
+            .send()
+            .map_err(|e| HostError::network("gitlab upload", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitlab upload", resp));
+        }
+
+        let parsed: GitLabCreateFileResp = resp.json().map_err(|e| e.to_string())?;
+        Ok(UploadedFile {
+            url: self.public_url(&parsed.file_path),
+            remote_path: parsed.file_path,
+        })
+    }
+
+    fn delete(&self, remote_path: &str) -> Result<(), HostError> {
+        let req_body = GitLabDeleteFileReq {
+            branch: self.branch.clone(),
+            commit_message: format!("chore(images): remove {}", remote_path),
+        };
+        let resp = self
+            .client
+            .delete(self.file_url(remote_path))
+            .header("PRIVATE-TOKEN", &self.token)
+            .json(&req_body)
+            .send()
+            .map_err(|e| HostError::network("gitlab delete", e))?;
+        if !resp.status().is_success() {
+            return Err(HostError::from_response("gitlab delete", resp));
+        }
+        Ok(())
+    }
+
+    fn public_url(&self, remote_path: &str) -> String {
+        if let Some(template) = &self.url_template {
+            let (owner, repo) = self.project.rsplit_once('/').unwrap_or(("", &self.project));
+            return url_templates::expand(
+                template,
+                &[
+                    ("project", &self.project),
+                    ("owner", owner),
+                    ("repo", repo),
+                    ("branch", &uri_encode(&self.branch, false)),
+                    ("path", &uri_encode(remote_path, false)),
+                ],
+            );
+        }
+        // Numeric ids only work through the API; path-based projects get the web raw URL.
+        if self.project.bytes().all(|b| b.is_ascii_digit()) {
+            return format!(
+                "{}/raw?ref={}",
+                self.file_url(remote_path),
+                uri_encode(&self.branch, true)
+            );
+        }
+        format!(
+            "{}/{}/-/raw/{}/{}",
+            self.base_url,
+            self.project,
+            uri_encode(&self.branch, false),
+            uri_encode(remote_path, false)
+        )
+    }
+}
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Manager};
+
+const NETWORK_SETTINGS_FILE: &str = "carbo-assets/network-settings.json";
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Proxy, certificate and timeout settings applied to every outbound
+/// request, stored in `$APPDATA/carbo-assets/network-settings.json`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct NetworkSettings {
+    /// Proxy for all requests: `http://`, `https://`, `socks5://` or
+    /// `socks5h://` (DNS through the proxy), optionally with `user:pass@`.
+    /// Without any proxy set, the `HTTP_PROXY`/`HTTPS_PROXY` environment
+    /// variables are used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Overrides `proxy` for `http://` URLs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_proxy: Option<String>,
+    /// Overrides `proxy` for `https://` URLs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub https_proxy: Option<String>,
+    /// Hosts that bypass the proxy, in `NO_PROXY` syntax: `example.com`
+    /// (and its subdomains), `.example.com`, IPs or CIDR ranges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+    /// PEM files with extra root certificates, trusted alongside the
+    /// built-in ones, e.g. a corporate TLS-inspection CA.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ca_bundles: Vec<String>,
+    /// Default per-request timeout for image hosts; 60 seconds when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+}
+
+type ProxyBuilder = fn(&str) -> reqwest::Result<reqwest::Proxy>;
+
+fn settings_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(NETWORK_SETTINGS_FILE, BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl NetworkSettings {
+    /// The saved settings, or the defaults when none are saved or the file
+    /// cannot be read.
+    pub(crate) fn load(app: &tauri::AppHandle) -> Self {
+        settings_path(app)
+            .ok()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// `secs` when given, else the configured default.
+    pub(crate) fn timeout(&self, secs: Option<u64>) -> Duration {
+        Duration::from_secs(
+            secs.or(self.timeout_secs)
+                .unwrap_or(DEFAULT_TIMEOUT_SECS)
+                .max(1),
+        )
+    }
+
+    fn proxy(&self, url: &str, build: ProxyBuilder) -> Result<reqwest::Proxy, String> {
+        let proxy = build(url).map_err(|e| format!("invalid proxy {}: {}", url, e))?;
+        let no_proxy = self
+            .no_proxy
+            .iter()
+            .map(|host| host.trim())
+            .filter(|host| !host.is_empty())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&no_proxy)))
+    }
+
+    pub(crate) fn client(&self, timeout: Duration) -> Result<reqwest::blocking::Client, String> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent("Carbo Markdown Editor")
+            .timeout(timeout);
+        if let Some(secs) = self.connect_timeout_secs {
+            builder = builder.connect_timeout(Duration::from_secs(secs.max(1)));
+        }
+
+        let proxies: [(Option<&str>, ProxyBuilder); 3] = [
+            (non_empty(&self.http_proxy), |url| reqwest::Proxy::http(url)),
+            (non_empty(&self.https_proxy), |url| {
+                reqwest::Proxy::https(url)
+            }),
+            (non_empty(&self.proxy), |url| reqwest::Proxy::all(url)),
+        ];
+        // reqwest uses the first proxy that matches, so scheme-specific ones go first.
+        for (url, build) in proxies {
+            if let Some(url) = url {
+                builder = builder.proxy(self.proxy(url, build)?);
+            }
+        }
+
+        for path in self.ca_bundles.iter().filter(|p| !p.trim().is_empty()) {
+            let pem = std::fs::read(path.trim()).map_err(|e| format!("{}: {}", path, e))?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            if certs.is_empty() {
+                return Err(format!("{}: no certificates found", path));
+            }
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+/// A client using the saved network settings, with `timeout_secs` or the
+/// configured default timeout.
+pub(crate) fn client(
+    app: &tauri::AppHandle,
+    timeout_secs: Option<u64>,
+) -> Result<reqwest::blocking::Client, String> {
+    let settings = NetworkSettings::load(app);
+    settings.client(settings.timeout(timeout_secs))
+}
+
+#[tauri::command]
+pub(crate) fn read_network_settings(app: tauri::AppHandle) -> Result<NetworkSettings, String> {
+    let path = settings_path(&app)?;
+    if !path.is_file() {
+        return Ok(NetworkSettings::default());
+    }
+    let text = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// Saves the settings after checking that a client can be built from them,
+/// so a bad proxy URL or CA file is reported here rather than on upload.
+#[tauri::command]
+pub(crate) fn write_network_settings(
+    app: tauri::AppHandle,
+    settings: NetworkSettings,
+) -> Result<(), String> {
+    settings.client(settings.timeout(None))?;
+    let path = settings_path(&app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| e.to_string())
+}
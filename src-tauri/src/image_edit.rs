@@ -0,0 +1,309 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageFormat, Rgba};
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{assets, image_metadata, image_type, sanitize_git_path_component};
+
+const DEFAULT_PIXELATE_BLOCK: u32 = 12;
+const JPEG_QUALITY: u8 = 92;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum RedactMode {
+    #[default]
+    Pixelate,
+    /// Solid black box.
+    Fill,
+}
+
+/// A single edit, applied in order. Coordinates are in displayed (EXIF-rotated) pixels.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub(crate) enum ImageEditOp {
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// Clockwise rotation; multiples of 90 degrees.
+    Rotate {
+        degrees: i32,
+    },
+    FlipHorizontal,
+    FlipVertical,
+    /// Missing dimensions keep the aspect ratio.
+    Resize {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    Redact {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        mode: RedactMode,
+        block_size: Option<u32>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImageEditResult {
+    path: String,
+    width: u32,
+    height: u32,
+    /// Copy of the original in local history when edited in place.
+    backup_path: Option<String>,
+}
+
+/// Applies `operations` to the image at `path`. By default the result is
+/// written next to the source as `<stem>-edited.<ext>`; with `in_place` the
+/// source is overwritten after a backup is stored under
+/// `$APPDATA/carbo-assets/history`. The output is re-encoded, so no metadata
+/// (such as an unredacted EXIF thumbnail) survives.
+#[tauri::command]
+pub(crate) fn edit_image_asset(
+    app: tauri::AppHandle,
+    path: String,
+    operations: Vec<ImageEditOp>,
+    in_place: Option<bool>,
+) -> Result<ImageEditResult, String> {
+    let src = PathBuf::from(&path);
+    if !crate::is_allowed_image_extension(&src) {
+        return Err("unsupported image type".to_string());
+    }
+    let bytes = std::fs::read(&src).map_err(|e| e.to_string())?;
+    let kind = image_type::sniff_image_type(&bytes)
+        .ok_or_else(|| "file content is not a supported image".to_string())?;
+    let format = match kind {
+        image_type::ImageType::Svg => return Err("svg images cannot be edited".to_string()),
+        image_type::ImageType::Ico => return Err("icon images cannot be edited".to_string()),
+        _ => ImageFormat::from_extension(kind.extension())
+            .ok_or_else(|| "unsupported image type".to_string())?,
+    };
+    if is_animated_gif(&bytes, format) {
+        return Err("animated images cannot be edited".to_string());
+    }
+
+    let (mut img, _) = image_metadata::decode_oriented(&bytes)?;
+    for op in &operations {
+        img = apply_operation(img, op)?;
+    }
+    let encoded = encode(&img, format)?;
+
+    let (destination, backup_path) = if in_place.unwrap_or(false) {
+        let backup = backup_to_history(&app, &src, &bytes)?;
+        (src.clone(), Some(backup.to_string_lossy().to_string()))
+    } else {
+        (edited_sibling(&src, kind.extension())?, None)
+    };
+    std::fs::write(&destination, encoded).map_err(|e| e.to_string())?;
+
+    let (width, height) = img.dimensions();
+    Ok(ImageEditResult {
+        path: destination.to_string_lossy().to_string(),
+        width,
+        height,
+        backup_path,
+    })
+}
+
+fn is_animated_gif(bytes: &[u8], format: ImageFormat) -> bool {
+    use image::AnimationDecoder;
+
+    if format != ImageFormat::Gif {
+        return false;
+    }
+    image::codecs::gif::GifDecoder::new(Cursor::new(bytes))
+        .map(|d| d.into_frames().take(2).count() > 1)
+        .unwrap_or(false)
+}
+
+fn apply_operation(img: DynamicImage, op: &ImageEditOp) -> Result<DynamicImage, String> {
+    let (w, h) = img.dimensions();
+    match *op {
+        ImageEditOp::Crop {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            let (x, y, width, height) = clamp_rect(x, y, width, height, w, h)?;
+            Ok(img.crop_imm(x, y, width, height))
+        }
+        ImageEditOp::Rotate { degrees } => match degrees.rem_euclid(360) {
+            0 => Ok(img),
+            90 => Ok(img.rotate90()),
+            180 => Ok(img.rotate180()),
+            270 => Ok(img.rotate270()),
+            _ => Err("rotation must be a multiple of 90 degrees".to_string()),
+        },
+        ImageEditOp::FlipHorizontal => Ok(img.fliph()),
+        ImageEditOp::FlipVertical => Ok(img.flipv()),
+        ImageEditOp::Resize { width, height } => {
+            let (nw, nh) = match (width, height) {
+                (Some(nw), Some(nh)) => (nw, nh),
+                (Some(nw), None) => (nw, scale_dimension(h, nw, w)),
+                (None, Some(nh)) => (scale_dimension(w, nh, h), nh),
+                (None, None) => return Ok(img),
+            };
+            if nw == 0 || nh == 0 {
+                return Err("resize dimensions must be positive".to_string());
+            }
+            Ok(img.resize_exact(nw, nh, FilterType::Lanczos3))
+        }
+        ImageEditOp::Redact {
+            x,
+            y,
+            width,
+            height,
+            mode,
+            block_size,
+        } => {
+            let (x, y, width, height) = clamp_rect(x, y, width, height, w, h)?;
+            let mut img = img;
+            match mode {
+                RedactMode::Fill => fill_rect(&mut img, x, y, width, height),
+                RedactMode::Pixelate => pixelate_rect(
+                    &mut img,
+                    x,
+                    y,
+                    width,
+                    height,
+                    block_size.unwrap_or(DEFAULT_PIXELATE_BLOCK).max(2),
+                ),
+            }
+            Ok(img)
+        }
+    }
+}
+
+fn scale_dimension(other: u32, new: u32, old: u32) -> u32 {
+    ((other as f64) * (new as f64) / (old.max(1) as f64))
+        .round()
+        .max(1.0) as u32
+}
+
+fn clamp_rect(x: u32, y: u32, width: u32, height: u32, w: u32, h: u32) -> Result<(u32, u32, u32, u32), String> {
+    if x >= w || y >= h {
+        return Err("rectangle lies outside the image".to_string());
+    }
+    let width = width.min(w - x);
+    let height = height.min(h - y);
+    if width == 0 || height == 0 {
+        return Err("rectangle is empty".to_string());
+    }
+    Ok((x, y, width, height))
+}
+
+fn fill_rect(img: &mut DynamicImage, x: u32, y: u32, width: u32, height: u32) {
+    for py in y..y + height {
+        for px in x..x + width {
+            img.put_pixel(px, py, Rgba([0, 0, 0, 255]));
+        }
+    }
+}
+
+// Replaces each block with its average colour.
+fn pixelate_rect(img: &mut DynamicImage, x: u32, y: u32, width: u32, height: u32, block: u32) {
+    let mut by = y;
+    while by < y + height {
+        let bh = block.min(y + height - by);
+        let mut bx = x;
+        while bx < x + width {
+            let bw = block.min(x + width - bx);
+            let mut sum = [0u64; 4];
+            for py in by..by + bh {
+                for px in bx..bx + bw {
+                    let p = img.get_pixel(px, py).0;
+                    for (s, v) in sum.iter_mut().zip(p) {
+                        *s += u64::from(v);
+                    }
+                }
+            }
+            let n = u64::from(bw * bh);
+            let avg = Rgba(sum.map(|s| (s / n) as u8));
+            for py in by..by + bh {
+                for px in bx..bx + bw {
+                    img.put_pixel(px, py, avg);
+                }
+            }
+            bx += bw;
+        }
+        by += bh;
+    }
+}
+
+fn encode(img: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+            let enc = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+            rgb.write_with_encoder(enc).map_err(|e| e.to_string())?;
+        }
+        ImageFormat::WebP | ImageFormat::Avif | ImageFormat::Gif => {
+            let rgba = DynamicImage::ImageRgba8(img.to_rgba8());
+            rgba.write_to(&mut Cursor::new(&mut out), format)
+                .map_err(|e| e.to_string())?;
+        }
+        _ => {
+            img.write_to(&mut Cursor::new(&mut out), format)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(out)
+}
+
+fn edited_sibling(src: &Path, extension: &str) -> Result<PathBuf, String> {
+    let dir = src
+        .parent()
+        .ok_or_else(|| "image has no parent directory".to_string())?;
+    let stem = src
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("image");
+    let stem = sanitize_git_path_component(stem);
+    for n in 1..10_000u32 {
+        let name = if n == 1 {
+            format!("{}-edited.{}", stem, extension)
+        } else {
+            format!("{}-edited-{}.{}", stem, n, extension)
+        };
+        let candidate = dir.join(name);
+        if !candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+    Err("unable to find a free file name".to_string())
+}
+
+fn backup_to_history(app: &tauri::AppHandle, src: &Path, bytes: &[u8]) -> Result<PathBuf, String> {
+    let canonical = std::fs::canonicalize(src).unwrap_or_else(|_| src.to_path_buf());
+    let key = assets::content_hash(canonical.to_string_lossy().as_bytes());
+    let dir = app
+        .path()
+        .resolve(format!("carbo-assets/history/{}", key), BaseDirectory::AppData)
+        .map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis();
+    let name = src
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image");
+    let backup = dir.join(format!("{}-{}", ts, crate::sanitize_file_name(name)));
+    std::fs::write(&backup, bytes).map_err(|e| e.to_string())?;
+    Ok(backup)
+}
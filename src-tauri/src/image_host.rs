@@ -0,0 +1,423 @@
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::command_host::{CommandHost, CommandHostRef};
+use crate::gitee_host::{GiteeHost, GiteeHostConfig};
+use crate::github_host::{GitHubHost, GitHubHostConfig};
+use crate::gitlab_host::{GitLabHost, GitLabHostConfig};
+use crate::http_client::NetworkSettings;
+use crate::s3_host::{S3Host, S3HostConfig};
+use crate::upload_cache::UploadCache;
+use crate::upload_history;
+use crate::upload_tasks::{self, UploadRegistry};
+use crate::{
+    credentials, image_pipeline, image_type, sanitize_git_path_component, workspace_settings,
+};
+
+/// A remote destination for images.
+pub(crate) trait ImageHost: Send + Sync {
+    /// Identifies the destination (account, bucket, folder), so upload caches
+    /// are not shared between hosts.
+    fn target_key(&self) -> String;
+
+    /// Checks credentials and write access without uploading anything.
+    fn validate(&self) -> Result<HostValidation, HostError>;
+
+    /// Uploads `bytes` under `file_name`. Hosts may place or rename the file.
+    fn upload(&self, file_name: &str, bytes: &[u8]) -> Result<UploadedFile, HostError>;
+
+    /// Removes a file previously returned by `upload`.
+    fn delete(&self, remote_path: &str) -> Result<(), HostError>;
+
+    /// Removes a file whose git blob sha is known from the upload. Hosts that
+    /// address files by content (GitHub) refuse if it has changed since.
+    fn delete_blob(&self, remote_path: &str, _sha: &str) -> Result<(), HostError> {
+        self.delete(remote_path)
+    }
+
+    /// Finds a file under the host's prefix whose git blob sha is `sha`.
+    /// Hosts that cannot search by content return `None`.
+    fn find_by_blob_sha(&self, _sha: &str) -> Result<Option<UploadedFile>, HostError> {
+        Ok(None)
+    }
+
+    /// Public URL for a remote path.
+    fn public_url(&self, remote_path: &str) -> String;
+}
+
+/// Host selection as sent by the frontend, e.g. `{ "kind": "github", "repo": ... }`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum HostConfig {
+    Github(GitHubHostConfig),
+    /// GitLab.com or a self-managed instance.
+    Gitlab(GitLabHostConfig),
+    /// Gitee or a self-hosted Gitee instance.
+    Gitee(GiteeHostConfig),
+    /// AWS S3 or a compatible store (MinIO, Cloudflare R2, Aliyun OSS).
+    S3(S3HostConfig),
+    /// An external uploader such as PicGo-CLI, saved with `write_upload_command`.
+    Command(CommandHostRef),
+}
+
+impl HostConfig {
+    /// The keyring key and the token or secret field it fills, for hosts that
+    /// authenticate with one.
+    pub(crate) fn secret_mut(&mut self) -> Option<(String, &mut String)> {
+        match self {
+            HostConfig::Github(config) => Some((config.credential_key(), &mut config.token)),
+            HostConfig::Gitlab(config) => Some((config.credential_key(), &mut config.token)),
+            HostConfig::Gitee(config) => Some((config.credential_key(), &mut config.token)),
+            HostConfig::S3(config) => {
+                Some((config.credential_key(), &mut config.secret_access_key))
+            }
+            HostConfig::Command(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UploadedFile {
+    /// Host-specific location, accepted by `delete` and `public_url`.
+    pub remote_path: String,
+    pub url: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HostValidation {
+    pub writable: bool,
+    /// Why the host is not usable, when it is not.
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum HostErrorKind {
+    /// Missing, invalid or insufficient credentials.
+    Auth,
+    NotFound,
+    RateLimited,
+    /// The remote state changed underneath the request, e.g. a file already exists.
+    Conflict,
+    /// The request never got a response.
+    Network,
+    /// The host answered with a 5xx.
+    Server,
+    Cancelled,
+    Other,
+}
+
+/// Error returned to the frontend by host commands, so it can react to the
+/// kind of failure instead of parsing messages.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HostError {
+    pub kind: HostErrorKind,
+    pub message: String,
+    /// HTTP status, when the host answered.
+    pub status: Option<u16>,
+    /// Seconds until a rate-limited request may be retried.
+    pub retry_after_secs: Option<u64>,
+}
+
+impl HostError {
+    pub(crate) fn new(kind: HostErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            status: None,
+            retry_after_secs: None,
+        }
+    }
+
+    /// A request that got no response. The URL is left out of the message:
+    /// some hosts (Gitee) take the token in the query string.
+    pub(crate) fn network(context: &str, error: reqwest::Error) -> Self {
+        Self::new(
+            HostErrorKind::Network,
+            format!("{} failed: {}", context, error.without_url()),
+        )
+    }
+
+    /// Classifies an unsuccessful response. `body` is reduced to the JSON
+    /// `message` most hosts return, when there is one.
+    pub(crate) fn http(context: &str, status: reqwest::StatusCode, body: &str) -> Self {
+        let kind = match status.as_u16() {
+            401 | 403 => HostErrorKind::Auth,
+            404 => HostErrorKind::NotFound,
+            409 => HostErrorKind::Conflict,
+            429 => HostErrorKind::RateLimited,
+            500..=599 => HostErrorKind::Server,
+            _ => HostErrorKind::Other,
+        };
+        let detail = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| {
+                v.get("message")
+                    .and_then(|m| m.as_str())
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| body.trim().chars().take(300).collect());
+        Self {
+            status: Some(status.as_u16()),
+            ..Self::new(kind, format!("{} failed: {} {}", context, status, detail))
+        }
+    }
+
+    /// Reads the body of an unsuccessful response into an error.
+    pub(crate) fn from_response(context: &str, resp: reqwest::blocking::Response) -> Self {
+        let status = resp.status();
+        let body = resp.text().unwrap_or_default();
+        Self::http(context, status, &body)
+    }
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for HostError {
+    fn from(message: String) -> Self {
+        Self::new(HostErrorKind::Other, message)
+    }
+}
+
+impl From<HostError> for String {
+    fn from(error: HostError) -> Self {
+        error.message
+    }
+}
+
+/// Processing applied before an image leaves the machine.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct UploadOptions {
+    pub pipeline: Option<image_pipeline::ImagePipelineOptions>,
+    /// Defaults to the workspace setting.
+    pub strip_metadata: Option<bool>,
+    /// Per-request timeout; defaults to the network settings', else 60 seconds.
+    pub timeout_secs: Option<u64>,
+    /// Also look for identical content already on the host (for GitHub, in
+    /// the branch's tree) before uploading. Costs a request per upload.
+    pub check_remote: bool,
+    /// Document the image is for, recorded in the upload history.
+    pub document_path: Option<String>,
+}
+
+/// Builds the host with an HTTP client from the saved network settings.
+/// `timeout_secs` overrides their request timeout.
+pub(crate) fn build_host(
+    app: &tauri::AppHandle,
+    config: &HostConfig,
+    timeout_secs: Option<u64>,
+) -> Result<Box<dyn ImageHost>, HostError> {
+    build_cancellable_host(app, config, timeout_secs, &Arc::default())
+}
+
+/// `build_host` for an upload that `cancelled` can stop while the host
+/// waits between retries.
+pub(crate) fn build_cancellable_host(
+    app: &tauri::AppHandle,
+    config: &HostConfig,
+    timeout_secs: Option<u64>,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<Box<dyn ImageHost>, HostError> {
+    let network = NetworkSettings::load(app);
+    let timeout = network.timeout(timeout_secs);
+    let client = || network.client(timeout);
+    Ok(match config {
+        HostConfig::Github(config) => {
+            Box::new(GitHubHost::new(config, client()?, cancelled.clone())?)
+        }
+        HostConfig::Gitlab(config) => Box::new(GitLabHost::new(config, client()?)?),
+        HostConfig::Gitee(config) => Box::new(GiteeHost::new(config, client()?)?),
+        HostConfig::S3(config) => Box::new(S3Host::new(config, client()?)?),
+        HostConfig::Command(command) => {
+            Box::new(CommandHost::new(&command.resolve(app)?, timeout)?)
+        }
+    })
+}
+
+/// Runs network work off the async runtime's worker threads.
+pub(crate) async fn run_blocking<T, E, F>(work: F) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<String> + Send + 'static,
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| E::from(e.to_string()))?
+}
+
+pub(crate) fn read_upload_source(src: &Path) -> Result<Vec<u8>, String> {
+    let meta = std::fs::metadata(src).map_err(|e| e.to_string())?;
+    if !meta.is_file() {
+        return Err("not a file".to_string());
+    }
+    std::fs::read(src).map_err(|e| e.to_string())
+}
+
+/// Validates, strips and (when needed) re-encodes an image read from `src`
+/// for upload. Returns the bytes and their extension.
+pub(crate) fn prepare_upload_bytes(
+    src: &Path,
+    original: Vec<u8>,
+    max_bytes: u64,
+    options: &UploadOptions,
+) -> Result<(Vec<u8>, String), String> {
+    let (original, ext) = image_type::validate_image(original, Some(crate::file_extension(src)))?;
+    let original = crate::strip_image_metadata_if(
+        original,
+        workspace_settings::resolve_strip_metadata(options.strip_metadata, Some(src)),
+    )?;
+
+    if options.pipeline.is_none() && (original.len() as u64) <= max_bytes {
+        return Ok((original, ext));
+    }
+    // Without explicit options this is the legacy best effort: JPEG under the limit,
+    // except that SVGs and animations keep their format.
+    let mut pipeline = options.pipeline.clone().unwrap_or_default();
+    pipeline.max_bytes = Some(pipeline.max_bytes.map_or(max_bytes, |b| b.min(max_bytes)));
+    let processed = image_pipeline::process_image(&original, &ext, &pipeline)?;
+    Ok((processed.bytes, processed.extension))
+}
+
+/// `<ts>-<stem>.<ext>`, safe as a path component on every host.
+pub(crate) fn remote_file_name(stem: &str, ext: &str) -> Result<String, String> {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis();
+    Ok(format!(
+        "{}-{}.{}",
+        ts,
+        sanitize_git_path_component(stem),
+        ext
+    ))
+}
+
+/// Splits `owner/repo`.
+pub(crate) fn parse_owner_repo(repo: &str) -> Result<(String, String), String> {
+    let trimmed = repo.trim();
+    let parts: Vec<&str> = trimmed.split('/').filter(|p| !p.is_empty()).collect();
+    if parts.len() != 2 {
+        return Err("repo must be in the format owner/repo".to_string());
+    }
+    Ok((parts[0].to_string(), parts[1].to_string()))
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters, optionally
+/// keeping `/`. This is also the encoding SigV4 signs.
+pub(crate) fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[tauri::command]
+pub(crate) async fn validate_image_host(
+    app: tauri::AppHandle,
+    mut host: HostConfig,
+    timeout_secs: Option<u64>,
+) -> Result<HostValidation, HostError> {
+    run_blocking(move || {
+        credentials::fill_host_secret(&app, &mut host)?;
+        build_host(&app, &host, timeout_secs)?.validate()
+    })
+    .await
+}
+
+/// Uploads one local image; without `max_bytes` the size is not limited.
+/// Progress is reported through `carbo-upload-status` events under `upload_id`,
+/// which `cancel_image_upload` accepts.
+#[tauri::command]
+pub(crate) async fn upload_image_to_host(
+    app: tauri::AppHandle,
+    registry: tauri::State<'_, UploadRegistry>,
+    host: HostConfig,
+    local_path: String,
+    max_bytes: Option<u64>,
+    options: Option<UploadOptions>,
+    upload_id: Option<String>,
+) -> Result<UploadedFile, HostError> {
+    if !crate::is_allowed_image_extension(Path::new(&local_path)) {
+        return Err(HostError::new(
+            HostErrorKind::Other,
+            "unsupported image type",
+        ));
+    }
+    upload_tasks::run_upload(
+        app,
+        &registry,
+        upload_id,
+        host,
+        local_path,
+        max_bytes.unwrap_or(u64::MAX),
+        options.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Deletes a hosted file and forgets it in the upload cache, so identical
+/// content is uploaded again next time.
+#[tauri::command]
+pub(crate) async fn delete_from_image_host(
+    app: tauri::AppHandle,
+    mut host: HostConfig,
+    remote_path: String,
+    timeout_secs: Option<u64>,
+) -> Result<(), HostError> {
+    run_blocking(move || {
+        credentials::fill_host_secret(&app, &mut host)?;
+        let host = build_host(&app, &host, timeout_secs)?;
+        host.delete(&remote_path)?;
+        UploadCache::open(&app)?.forget(&host.target_key(), &remote_path)?;
+        upload_history::record_deleted(&app, &host.target_key(), &remote_path)?;
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_errors_leave_out_the_url() {
+        // Nothing listens on the port once the listener is dropped.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/repos?access_token=s3cret", port);
+        let error = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .get(url)
+            .send()
+            .unwrap_err();
+        let error = HostError::network("gitee lookup", error);
+        assert_eq!(error.kind, HostErrorKind::Network);
+        assert!(error.message.starts_with("gitee lookup failed: "));
+        assert!(!error.message.contains("s3cret"), "{}", error.message);
+    }
+}
@@ -0,0 +1,376 @@
+use std::io::Cursor;
+use std::ops::Range;
+
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+
+use crate::image_type::{self, ImageType};
+
+// Quality used when a JPEG or lossy WebP has to be re-encoded to bake in its
+// orientation.
+const REENCODE_JPEG_QUALITY: u8 = 92;
+const REENCODE_WEBP_QUALITY: f32 = 90.0;
+
+/// Decodes `bytes`, applying the EXIF orientation so the pixels are upright.
+pub(crate) fn decode_oriented(bytes: &[u8]) -> Result<(DynamicImage, ImageFormat), String> {
+    let reader = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let format = reader
+        .format()
+        .ok_or_else(|| "unsupported image format".to_string())?;
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    img.apply_orientation(orientation);
+    Ok((img, format))
+}
+
+/// Removes privacy-sensitive metadata (EXIF/GPS, XMP, IPTC, comments) and bakes
+/// the EXIF orientation into the pixels. Formats without such metadata pass through.
+/// AVIF keeps its orientation in `irot`/`imir` properties, so only its
+/// metadata items are blanked.
+pub(crate) fn sanitize_image_metadata(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if image_type::sniff_image_type(bytes) == Some(ImageType::Avif) {
+        return strip_avif(bytes);
+    }
+    let Ok(format) = image::guess_format(bytes) else {
+        return Ok(bytes.to_vec());
+    };
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP) {
+        return Ok(bytes.to_vec());
+    }
+
+    if exif_orientation(bytes) != Orientation::NoTransforms {
+        // Re-encoding writes no metadata at all.
+        let (img, _) = decode_oriented(bytes)?;
+        return reencode(&img, format, bytes);
+    }
+
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(bytes),
+        ImageFormat::Png => strip_png(bytes),
+        _ => strip_webp(bytes),
+    }
+}
+
+fn exif_orientation(bytes: &[u8]) -> Orientation {
+    image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|r| r.into_decoder().ok())
+        .and_then(|mut d| d.orientation().ok())
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+fn reencode(img: &DynamicImage, format: ImageFormat, source: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+            let enc = image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut out,
+                REENCODE_JPEG_QUALITY,
+            );
+            rgb.write_with_encoder(enc).map_err(|e| e.to_string())?;
+        }
+        ImageFormat::WebP => {
+            let rgba = img.to_rgba8();
+            let encoder = webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height());
+            // Keep lossy sources lossy; a lossless copy would be many times larger.
+            let encoded = if is_lossless_webp(source) {
+                encoder.encode_lossless()
+            } else {
+                encoder.encode(REENCODE_WEBP_QUALITY)
+            };
+            out = encoded.to_vec();
+        }
+        _ => {
+            img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(out)
+}
+
+// Keeps JFIF (APP0), ICC profiles (APP2) and Adobe colour info (APP14); drops
+// every other APPn segment and comments.
+fn strip_jpeg(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let malformed = || "malformed jpeg".to_string();
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] != 0xD8 {
+        return Err(malformed());
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..2]);
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return Err(malformed());
+        }
+        let marker = bytes[pos + 1];
+        if marker == 0xFF {
+            // Fill byte.
+            pos += 1;
+            continue;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return Err(malformed());
+        }
+        if marker == 0xDA {
+            // Start of scan: the entropy-coded data and everything after is kept as-is.
+            out.extend_from_slice(&bytes[pos..]);
+            return Ok(out);
+        }
+        let drop = matches!(marker, 0xE1 | 0xE3..=0xED | 0xEF | 0xFE);
+        if !drop {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    Err(malformed())
+}
+
+fn strip_png(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return Err("malformed png".to_string());
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    while pos + 12 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+            as usize;
+        let end = pos + 12 + len;
+        if end > bytes.len() {
+            return Err("malformed png".to_string());
+        }
+        let kind = &bytes[pos + 4..pos + 8];
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+        if kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+// A `VP8L` bitstream chunk marks lossless WebP; lossy images use `VP8 `.
+fn is_lossless_webp(bytes: &[u8]) -> bool {
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind = &bytes[pos..pos + 4];
+        if kind == b"VP8L" {
+            return true;
+        }
+        if kind == b"VP8 " {
+            return false;
+        }
+        let len = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]])
+            as usize;
+        pos += 8 + len + (len & 1);
+    }
+    false
+}
+
+fn strip_webp(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    const VP8X_FLAG_EXIF: u8 = 0x08;
+    const VP8X_FLAG_XMP: u8 = 0x04;
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return Err("malformed webp".to_string());
+    }
+
+    let mut body = Vec::with_capacity(bytes.len());
+    body.extend_from_slice(b"WEBP");
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind = &bytes[pos..pos + 4];
+        let len = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]])
+            as usize;
+        // Chunks are padded to an even size.
+        let end = (pos + 8 + len + (len & 1)).min(bytes.len());
+        if pos + 8 + len > bytes.len() {
+            return Err("malformed webp".to_string());
+        }
+        if kind == b"EXIF" || kind == b"XMP " {
+            pos = end;
+            continue;
+        }
+        let start = body.len();
+        body.extend_from_slice(&bytes[pos..end]);
+        if kind == b"VP8X" && len >= 1 {
+            body[start + 8] &= !(VP8X_FLAG_EXIF | VP8X_FLAG_XMP);
+        }
+        pos = end;
+    }
+
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+// Reads an `n`-byte big-endian integer; `n` is 0 to 8.
+fn read_be(bytes: &[u8], pos: &mut usize, n: usize) -> Result<u64, String> {
+    let field = bytes
+        .get(*pos..*pos + n)
+        .ok_or_else(|| "malformed avif".to_string())?;
+    *pos += n;
+    Ok(field.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+}
+
+/// Version of the full box whose content is `range`.
+fn full_box_version(bytes: &[u8], range: &Range<usize>) -> Result<u8, String> {
+    if range.len() < 4 {
+        return Err("malformed avif".to_string());
+    }
+    Ok(bytes[range.start])
+}
+
+/// Content of the first `kind` box among the ISO-BMFF boxes in `range`.
+fn find_box(
+    bytes: &[u8],
+    range: Range<usize>,
+    kind: &[u8; 4],
+) -> Result<Option<Range<usize>>, String> {
+    let mut pos = range.start;
+    while pos + 8 <= range.end {
+        let start = pos;
+        let size = read_be(bytes, &mut pos, 4)? as usize;
+        let found = &bytes[pos..pos + 4] == kind;
+        pos += 4;
+        let end = match size {
+            0 => range.end,
+            1 => start.saturating_add(read_be(bytes, &mut pos, 8)? as usize),
+            _ => start.saturating_add(size),
+        };
+        if end < pos || end > range.end {
+            return Err("malformed avif".to_string());
+        }
+        if found {
+            return Ok(Some(pos..end));
+        }
+        pos = end;
+    }
+    Ok(None)
+}
+
+/// IDs of the EXIF and XMP items listed in an `iinf` box.
+fn metadata_item_ids(bytes: &[u8], iinf: Range<usize>) -> Result<Vec<u64>, String> {
+    let version = full_box_version(bytes, &iinf)?;
+    let mut pos = iinf.start + 4;
+    read_be(bytes, &mut pos, if version == 0 { 2 } else { 4 })?;
+
+    let mut ids = Vec::new();
+    let mut rest = pos..iinf.end;
+    while let Some(infe) = find_box(bytes, rest.clone(), b"infe")? {
+        rest = infe.end..iinf.end;
+        let version = full_box_version(bytes, &infe)?;
+        // Entries before version 2 carry no item type.
+        if version < 2 {
+            continue;
+        }
+        let mut pos = infe.start + 4;
+        let id = read_be(bytes, &mut pos, if version == 2 { 2 } else { 4 })?;
+        pos += 2; // item_protection_index
+        let item_type = bytes.get(pos..pos + 4).ok_or("malformed avif")?;
+        let is_metadata = match item_type {
+            b"Exif" => true,
+            b"mime" => {
+                // item_name and content_type are NUL-terminated strings.
+                let names = bytes.get(pos + 4..infe.end).unwrap_or_default();
+                let mut strings = names.split(|b| *b == 0);
+                strings.next();
+                strings.next() == Some(b"application/rdf+xml".as_slice())
+            }
+            _ => false,
+        };
+        if is_metadata {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+// HEIF stores EXIF and XMP as items of the `meta` box. Their payloads are
+// overwritten with zeros, which leaves every box size and offset valid.
+fn strip_avif(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let malformed = || "malformed avif".to_string();
+    let mut out = bytes.to_vec();
+    let Some(meta) = find_box(bytes, 0..bytes.len(), b"meta")? else {
+        return Ok(out);
+    };
+    // `meta` is a full box: version and flags precede its children.
+    full_box_version(bytes, &meta)?;
+    let children = meta.start + 4..meta.end;
+    let Some(iinf) = find_box(bytes, children.clone(), b"iinf")? else {
+        return Ok(out);
+    };
+    let ids = metadata_item_ids(bytes, iinf)?;
+    if ids.is_empty() {
+        return Ok(out);
+    }
+    let iloc = find_box(bytes, children.clone(), b"iloc")?.ok_or_else(malformed)?;
+    let idat = find_box(bytes, children, b"idat")?;
+
+    let version = full_box_version(bytes, &iloc)?;
+    let mut pos = iloc.start + 4;
+    let sizes = read_be(bytes, &mut pos, 2)? as usize;
+    let (offset_size, length_size) = (sizes >> 12, (sizes >> 8) & 0xF);
+    let base_offset_size = (sizes >> 4) & 0xF;
+    let index_size = if version == 0 { 0 } else { sizes & 0xF };
+    if [offset_size, length_size, base_offset_size, index_size]
+        .iter()
+        .any(|size| ![0, 4, 8].contains(size))
+    {
+        return Err(malformed());
+    }
+    let id_size = if version < 2 { 2 } else { 4 };
+    let item_count = read_be(bytes, &mut pos, id_size)?;
+    for _ in 0..item_count {
+        let id = read_be(bytes, &mut pos, id_size)?;
+        let construction_method = if version == 0 {
+            0
+        } else {
+            read_be(bytes, &mut pos, 2)? & 0xF
+        };
+        read_be(bytes, &mut pos, 2)?; // data_reference_index
+        let base_offset = read_be(bytes, &mut pos, base_offset_size)? as usize;
+        let extent_count = read_be(bytes, &mut pos, 2)?;
+        for _ in 0..extent_count {
+            read_be(bytes, &mut pos, index_size)?;
+            let offset = read_be(bytes, &mut pos, offset_size)? as usize;
+            let length = read_be(bytes, &mut pos, length_size)? as usize;
+            if !ids.contains(&id) {
+                continue;
+            }
+            let container = match construction_method {
+                0 => 0..bytes.len(),
+                1 => idat.clone().ok_or_else(malformed)?,
+                _ => return Err("cannot strip metadata from this avif".to_string()),
+            };
+            let start = container
+                .start
+                .saturating_add(base_offset)
+                .saturating_add(offset);
+            // A zero length runs to the end of the container.
+            let end = if length == 0 {
+                container.end
+            } else {
+                start.saturating_add(length)
+            };
+            if start > end || end > container.end {
+                return Err(malformed());
+            }
+            out[start..end].fill(0);
+        }
+    }
+    Ok(out)
+}
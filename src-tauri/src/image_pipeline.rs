@@ -0,0 +1,486 @@
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+use image::{AnimationDecoder, DynamicImage, Frame, GenericImageView, ImageFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{image_metadata, svg};
+
+const DEFAULT_MAX_DIMENSION: u32 = 1920;
+const DEFAULT_MAX_QUALITY: u8 = 85;
+const DEFAULT_MIN_QUALITY: u8 = 45;
+// How many times the image is scaled down when even the lowest quality misses the budget.
+const MAX_DOWNSCALE_STEPS: u32 = 4;
+const DOWNSCALE_FACTOR: f32 = 0.75;
+const AVIF_SPEED: u8 = 10;
+
+/// Target encoding for processed images.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum OutputFormat {
+    /// Keep the source format; untouched when no resize or re-encode is needed.
+    #[default]
+    Original,
+    Png,
+    /// Lossy and opaque: transparency is flattened.
+    Jpeg,
+    Webp,
+    Avif,
+    /// Try every format that preserves the image and keep the smallest result.
+    Smallest,
+}
+
+/// Target encoding for animated images; frames are always preserved.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AnimationFormat {
+    #[default]
+    Gif,
+    Webp,
+}
+
+/// Options for the image processing pipeline used by uploads and local saves.
+///
+/// The defaults keep the source format, so transparency survives, with a
+/// 1920px cap and quality 85 down to 45.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct ImagePipelineOptions {
+    pub format: OutputFormat,
+    /// Largest allowed width or height; `0` disables resizing.
+    pub max_dimension: u32,
+    /// Byte budget; quality is binary-searched (then the image downscaled) to meet it.
+    pub max_bytes: Option<u64>,
+    pub max_quality: u8,
+    pub min_quality: u8,
+    /// Allow lossy palette quantization when a PNG misses the byte budget.
+    pub quantize_png: bool,
+    pub animation: AnimationFormat,
+}
+
+impl Default for ImagePipelineOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            max_bytes: None,
+            max_quality: DEFAULT_MAX_QUALITY,
+            min_quality: DEFAULT_MIN_QUALITY,
+            quantize_png: true,
+            animation: AnimationFormat::default(),
+        }
+    }
+}
+
+pub(crate) struct ProcessedImage {
+    pub bytes: Vec<u8>,
+    pub extension: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl Encoding {
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Png => "png",
+            Encoding::Jpeg => "jpg",
+            Encoding::Webp => "webp",
+            Encoding::Avif => "avif",
+        }
+    }
+}
+
+/// Runs `bytes` through the pipeline. `extension` is the source file extension,
+/// returned unchanged when the image is passed through as-is.
+///
+/// SVGs are minified as text and animated GIF/WebP keep their frames; when such
+/// an image cannot be brought under the byte budget an error is returned rather
+/// than flattening it.
+pub(crate) fn process_image(
+    bytes: &[u8],
+    extension: &str,
+    options: &ImagePipelineOptions,
+) -> Result<ProcessedImage, String> {
+    let passthrough = || ProcessedImage {
+        bytes: bytes.to_vec(),
+        extension: extension.to_ascii_lowercase(),
+    };
+    let within_budget = |len: usize| options.max_bytes.is_none_or(|max| len as u64 <= max);
+
+    if svg::looks_like_svg(bytes) {
+        if within_budget(bytes.len()) {
+            return Ok(passthrough());
+        }
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        let minified = svg::minify_svg(text);
+        if !within_budget(minified.len()) {
+            return Err("svg cannot be minified under the size limit".to_string());
+        }
+        return Ok(ProcessedImage {
+            bytes: minified.into_bytes(),
+            extension: "svg".to_string(),
+        });
+    }
+
+    if let Some((frames, source_format)) = decode_animation(bytes)? {
+        let keep_source = matches!(
+            (options.animation, source_format),
+            (AnimationFormat::Gif, ImageFormat::Gif) | (AnimationFormat::Webp, ImageFormat::WebP)
+        );
+        let (w, h) = frames[0].buffer().dimensions();
+        let fits = options.max_dimension == 0 || w.max(h) <= options.max_dimension;
+        if keep_source && fits && within_budget(bytes.len()) {
+            return Ok(passthrough());
+        }
+        return process_animation(&frames, options);
+    }
+
+    let Ok((mut img, source_format)) = image_metadata::decode_oriented(bytes) else {
+        // Formats we cannot rasterize are only ever passed through.
+        if options.format == OutputFormat::Original && within_budget(bytes.len()) {
+            return Ok(passthrough());
+        }
+        return Err("unsupported image format".to_string());
+    };
+
+    let resized = resize_to_fit(&mut img, options.max_dimension);
+    if options.format == OutputFormat::Original && !resized && within_budget(bytes.len()) {
+        return Ok(passthrough());
+    }
+
+    let candidates: Vec<Encoding> = match options.format {
+        OutputFormat::Original => vec![match source_format {
+            ImageFormat::Jpeg => Encoding::Jpeg,
+            ImageFormat::WebP => Encoding::Webp,
+            ImageFormat::Avif => Encoding::Avif,
+            _ => Encoding::Png,
+        }],
+        OutputFormat::Png => vec![Encoding::Png],
+        OutputFormat::Jpeg => vec![Encoding::Jpeg],
+        OutputFormat::Webp => vec![Encoding::Webp],
+        OutputFormat::Avif => vec![Encoding::Avif],
+        OutputFormat::Smallest => {
+            let mut all = vec![Encoding::Png, Encoding::Webp, Encoding::Avif];
+            if !uses_alpha(&img) {
+                all.push(Encoding::Jpeg);
+            }
+            all
+        }
+    };
+
+    let mut best: Option<ProcessedImage> = None;
+    for encoding in candidates {
+        let Some(out) = encode_within_budget(&img, encoding, options)? else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|b| out.len() < b.bytes.len()) {
+            best = Some(ProcessedImage {
+                bytes: out,
+                extension: encoding.extension().to_string(),
+            });
+        }
+    }
+
+    best.ok_or_else(|| "unable to compress image under size limit".to_string())
+}
+
+/// Decodes every frame of an animated GIF or WebP; `None` for still images.
+fn decode_animation(bytes: &[u8]) -> Result<Option<(Vec<Frame>, ImageFormat)>, String> {
+    match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => {
+            let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(bytes))
+                .map_err(|e| e.to_string())?;
+            let frames = decoder
+                .into_frames()
+                .collect_frames()
+                .map_err(|e| e.to_string())?;
+            Ok((frames.len() > 1).then_some((frames, ImageFormat::Gif)))
+        }
+        Ok(ImageFormat::WebP) => {
+            let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(bytes))
+                .map_err(|e| e.to_string())?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            let frames = decoder
+                .into_frames()
+                .collect_frames()
+                .map_err(|e| e.to_string())?;
+            Ok((frames.len() > 1).then_some((frames, ImageFormat::WebP)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn process_animation(
+    frames: &[Frame],
+    options: &ImagePipelineOptions,
+) -> Result<ProcessedImage, String> {
+    let (w, h) = frames[0].buffer().dimensions();
+    let largest = w.max(h);
+    let mut scale = if options.max_dimension == 0 || largest <= options.max_dimension {
+        1.0
+    } else {
+        options.max_dimension as f32 / largest as f32
+    };
+
+    let budget = options.max_bytes.map(|b| b as usize);
+    let max_quality = options.max_quality.clamp(1, 100);
+    let min_quality = options.min_quality.clamp(1, max_quality);
+
+    for _ in 0..=MAX_DOWNSCALE_STEPS {
+        let scaled = scale_frames(frames, scale);
+        let attempts: Vec<Vec<u8>> = match options.animation {
+            AnimationFormat::Gif => vec![encode_gif(&scaled)?],
+            AnimationFormat::Webp => {
+                let mut out = vec![encode_animated_webp(&scaled, max_quality)?];
+                if budget.is_some() && min_quality < max_quality {
+                    out.push(encode_animated_webp(&scaled, min_quality)?);
+                }
+                out
+            }
+        };
+        for out in attempts {
+            if budget.is_none_or(|b| out.len() <= b) {
+                let extension = match options.animation {
+                    AnimationFormat::Gif => "gif",
+                    AnimationFormat::Webp => "webp",
+                };
+                return Ok(ProcessedImage {
+                    bytes: out,
+                    extension: extension.to_string(),
+                });
+            }
+        }
+        scale *= DOWNSCALE_FACTOR;
+    }
+
+    Err("animated image cannot be reduced under the size limit".to_string())
+}
+
+fn scale_frames(frames: &[Frame], scale: f32) -> Vec<Frame> {
+    frames
+        .iter()
+        .map(|frame| {
+            let buffer = frame.buffer();
+            if scale >= 1.0 {
+                return Frame::from_parts(buffer.clone(), 0, 0, frame.delay());
+            }
+            let nw = ((buffer.width() as f32) * scale).round().max(1.0) as u32;
+            let nh = ((buffer.height() as f32) * scale).round().max(1.0) as u32;
+            let resized = image::imageops::resize(buffer, nw, nh, FilterType::Triangle);
+            Frame::from_parts(resized, 0, 0, frame.delay())
+        })
+        .collect()
+}
+
+fn encode_gif(frames: &[Frame]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    {
+        let mut enc = image::codecs::gif::GifEncoder::new_with_speed(&mut out, 10);
+        enc.set_repeat(image::codecs::gif::Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        enc.encode_frames(frames.iter().cloned())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
+
+fn encode_animated_webp(frames: &[Frame], quality: u8) -> Result<Vec<u8>, String> {
+    let mut config = webp::WebPConfig::new().map_err(|_| "invalid webp config".to_string())?;
+    config.quality = quality as f32;
+    let (w, h) = frames[0].buffer().dimensions();
+    let mut enc = webp::AnimEncoder::new(w, h, &config);
+    enc.set_loop_count(0);
+    let mut timestamp = 0i32;
+    for frame in frames {
+        enc.add_frame(webp::AnimFrame::from_rgba(
+            frame.buffer().as_raw(),
+            w,
+            h,
+            timestamp,
+        ));
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        timestamp += (numer / denom.max(1)) as i32;
+    }
+    let out = enc.try_encode().map_err(|e| format!("{:?}", e))?;
+    Ok(out.to_vec())
+}
+
+fn resize_to_fit(img: &mut DynamicImage, max_dimension: u32) -> bool {
+    let (w, h) = img.dimensions();
+    let largest = w.max(h);
+    if max_dimension == 0 || largest <= max_dimension {
+        return false;
+    }
+    let scale = max_dimension as f32 / largest as f32;
+    let nw = ((w as f32) * scale).round().max(1.0) as u32;
+    let nh = ((h as f32) * scale).round().max(1.0) as u32;
+    *img = img.resize(nw, nh, FilterType::Lanczos3);
+    true
+}
+
+fn uses_alpha(img: &DynamicImage) -> bool {
+    img.color().has_alpha() && img.to_rgba8().pixels().any(|p| p.0[3] < u8::MAX)
+}
+
+fn encode_within_budget(
+    img: &DynamicImage,
+    encoding: Encoding,
+    options: &ImagePipelineOptions,
+) -> Result<Option<Vec<u8>>, String> {
+    let max_quality = options.max_quality.clamp(1, 100);
+    let min_quality = options.min_quality.clamp(1, max_quality);
+
+    let Some(budget) = options.max_bytes.map(|b| b as usize) else {
+        return encode(img, encoding, max_quality).map(Some);
+    };
+
+    let mut current = img.clone();
+    for step in 0..=MAX_DOWNSCALE_STEPS {
+        if step > 0 {
+            let (w, h) = current.dimensions();
+            if w <= 1 && h <= 1 {
+                break;
+            }
+            let nw = ((w as f32) * DOWNSCALE_FACTOR).round().max(1.0) as u32;
+            let nh = ((h as f32) * DOWNSCALE_FACTOR).round().max(1.0) as u32;
+            current = current.resize_exact(nw, nh, FilterType::Lanczos3);
+        }
+
+        if encoding == Encoding::Png {
+            let lossless = encode(&current, Encoding::Png, max_quality)?;
+            if lossless.len() <= budget {
+                return Ok(Some(lossless));
+            }
+            if options.quantize_png {
+                let quantized = encode_png(&quantize(&current))?;
+                if quantized.len() <= budget {
+                    return Ok(Some(quantized));
+                }
+            }
+            continue;
+        }
+
+        // Highest quality that fits the budget.
+        let top = encode(&current, encoding, max_quality)?;
+        if top.len() <= budget {
+            return Ok(Some(top));
+        }
+        let (mut lo, mut hi) = (min_quality, max_quality - 1);
+        let mut found: Option<Vec<u8>> = None;
+        while lo <= hi {
+            let mid = lo + (hi - lo) / 2;
+            let out = encode(&current, encoding, mid)?;
+            if out.len() <= budget {
+                found = Some(out);
+                lo = mid + 1;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    Ok(None)
+}
+
+fn encode(img: &DynamicImage, encoding: Encoding, quality: u8) -> Result<Vec<u8>, String> {
+    match encoding {
+        Encoding::Png => encode_png(img),
+        Encoding::Jpeg => {
+            // JPEG has no alpha channel.
+            let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+            let mut out = Vec::new();
+            let enc = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
+            rgb.write_with_encoder(enc).map_err(|e| e.to_string())?;
+            Ok(out)
+        }
+        Encoding::Webp => {
+            let (w, h) = img.dimensions();
+            let out = if uses_alpha(img) {
+                let rgba = img.to_rgba8();
+                webp::Encoder::from_rgba(rgba.as_raw(), w, h).encode(quality as f32)
+            } else {
+                let rgb = img.to_rgb8();
+                webp::Encoder::from_rgb(rgb.as_raw(), w, h).encode(quality as f32)
+            };
+            Ok(out.to_vec())
+        }
+        Encoding::Avif => {
+            let rgba = DynamicImage::ImageRgba8(img.to_rgba8());
+            let mut out = Vec::new();
+            let enc = image::codecs::avif::AvifEncoder::new_with_speed_quality(
+                &mut out, AVIF_SPEED, quality,
+            );
+            rgba.write_with_encoder(enc).map_err(|e| e.to_string())?;
+            Ok(out)
+        }
+    }
+}
+
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut raw = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut raw), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    let mut opts = oxipng::Options::from_preset(2);
+    opts.strip = oxipng::StripChunks::Safe;
+    oxipng::optimize_from_memory(&raw, &opts).map_err(|e| e.to_string())
+}
+
+// Reduces the image to a 256-colour palette; oxipng then stores it as an indexed PNG.
+fn quantize(img: &DynamicImage) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let quant = color_quant::NeuQuant::new(10, 256, rgba.as_raw());
+    for pixel in rgba.pixels_mut() {
+        let idx = quant.index_of(&pixel.0);
+        if let Some(color) = quant.lookup(idx) {
+            pixel.0 = color;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn transparent_png(width: u32, height: u32) -> Vec<u8> {
+        let img = ImageBuffer::from_fn(width, height, |x, _| {
+            Rgba([255, 0, 0, if x % 2 == 0 { 0 } else { 255 }])
+        });
+        let mut out = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut out, ImageFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn default_pipeline_keeps_png_transparency() {
+        let options: ImagePipelineOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.format, OutputFormat::Original);
+
+        // Large enough to be resized, so the image is re-encoded.
+        let source = transparent_png(DEFAULT_MAX_DIMENSION * 2, 8);
+        let processed = process_image(&source, "png", &options).unwrap();
+        assert_eq!(processed.extension, "png");
+        let decoded = image::load_from_memory(&processed.bytes).unwrap();
+        assert_eq!(decoded.width(), DEFAULT_MAX_DIMENSION);
+        assert!(decoded.color().has_alpha());
+        assert!(decoded.to_rgba8().pixels().any(|p| p[3] < 255));
+
+        let small = transparent_png(16, 16);
+        let processed = process_image(&small, "png", &options).unwrap();
+        assert_eq!(processed.bytes, small);
+    }
+}
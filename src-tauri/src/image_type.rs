@@ -0,0 +1,132 @@
+use crate::svg;
+
+/// Image types accepted on ingest, identified by content rather than extension.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ImageType {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Avif,
+    Bmp,
+    Ico,
+    Svg,
+}
+
+impl ImageType {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            ImageType::Png => "png",
+            ImageType::Jpeg => "jpg",
+            ImageType::Gif => "gif",
+            ImageType::Webp => "webp",
+            ImageType::Avif => "avif",
+            ImageType::Bmp => "bmp",
+            ImageType::Ico => "ico",
+            ImageType::Svg => "svg",
+        }
+    }
+
+    pub(crate) fn mime_type(self) -> &'static str {
+        match self {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::Webp => "image/webp",
+            ImageType::Avif => "image/avif",
+            ImageType::Bmp => "image/bmp",
+            ImageType::Ico => "image/x-icon",
+            ImageType::Svg => "image/svg+xml",
+        }
+    }
+
+    pub(crate) fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageType::Png),
+            "jpg" | "jpeg" => Some(ImageType::Jpeg),
+            "gif" => Some(ImageType::Gif),
+            "webp" => Some(ImageType::Webp),
+            "avif" => Some(ImageType::Avif),
+            "bmp" => Some(ImageType::Bmp),
+            "ico" => Some(ImageType::Ico),
+            "svg" => Some(ImageType::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies the image type from magic bytes (or SVG markup).
+pub(crate) fn sniff_image_type(bytes: &[u8]) -> Option<ImageType> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ImageType::Png);
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(ImageType::Jpeg);
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(ImageType::Gif);
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some(ImageType::Webp);
+    }
+    if is_avif(bytes) {
+        return Some(ImageType::Avif);
+    }
+    if bytes.len() >= 14 && bytes.starts_with(b"BM") {
+        return Some(ImageType::Bmp);
+    }
+    if bytes.len() >= 6 && bytes.starts_with(&[0, 0, 1, 0]) && bytes[4..6] != [0, 0] {
+        return Some(ImageType::Ico);
+    }
+    if svg::looks_like_svg(bytes) {
+        return Some(ImageType::Svg);
+    }
+    None
+}
+
+// ISO-BMFF `ftyp` box with an AVIF major or compatible brand.
+fn is_avif(bytes: &[u8]) -> bool {
+    if bytes.len() < 16 || &bytes[4..8] != b"ftyp" {
+        return false;
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let end = size.clamp(16, bytes.len());
+    let major = &bytes[8..12];
+    major == b"avif"
+        || major == b"avis"
+        || bytes[16..end]
+            .chunks_exact(4)
+            .any(|brand| brand == b"avif" || brand == b"avis")
+}
+
+/// Validates image bytes on ingest and returns them with the extension of the
+/// sniffed type. SVGs are sanitized.
+///
+/// `claimed_extension` is the extension of the file on disk or of the name the
+/// webview sent: when it names a different image type than the content, the
+/// file is rejected. `None` leaves the type to the content.
+pub(crate) fn validate_image(
+    bytes: Vec<u8>,
+    claimed_extension: Option<&str>,
+) -> Result<(Vec<u8>, String), String> {
+    let kind =
+        sniff_image_type(&bytes).ok_or_else(|| "file content is not a supported image".to_string())?;
+
+    if let Some(claimed) = claimed_extension {
+        if ImageType::from_extension(claimed) != Some(kind) {
+            return Err(format!(
+                "file extension .{} does not match its {} content",
+                claimed.to_ascii_lowercase(),
+                kind.extension()
+            ));
+        }
+    }
+
+    let bytes = if kind == ImageType::Svg {
+        let text = std::str::from_utf8(&bytes).map_err(|e| e.to_string())?;
+        svg::sanitize_svg(text).into_bytes()
+    } else {
+        bytes
+    };
+    Ok((bytes, kind.extension().to_string()))
+}
@@ -2,12 +2,38 @@ use std::path::PathBuf;
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use base64::Engine;
-use image::GenericImageView;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tauri::{path::BaseDirectory, Emitter, Manager};
 
-const GITHUB_API_VERSION: &str = "2022-11-28";
+mod assets;
+mod batch_upload;
+mod command_host;
+mod credentials;
+mod gallery;
+mod gitee_host;
+mod github_batch;
+mod github_host;
+mod gitlab_host;
+mod http_client;
+mod image_edit;
+mod image_host;
+mod image_metadata;
+mod image_pipeline;
+mod image_type;
+mod localize;
+mod markdown_images;
+mod remote_images;
+mod s3_host;
+mod svg;
+mod upload_cache;
+mod upload_history;
+mod upload_queue;
+mod upload_tasks;
+mod url_templates;
+mod workspace_settings;
+
+/// Largest image file read into memory when copying it into the assets.
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
 
 struct StartupOpenPaths(Mutex<Vec<String>>);
 
@@ -53,15 +79,6 @@ where
     out
 }
 
-fn parse_github_repo(repo: &str) -> Result<(String, String), String> {
-    let trimmed = repo.trim();
-    let parts: Vec<&str> = trimmed.split('/').filter(|p| !p.is_empty()).collect();
-    if parts.len() != 2 {
-        return Err("repo must be in the format owner/repo".to_string());
-    }
-    Ok((parts[0].to_string(), parts[1].to_string()))
-}
-
 fn sanitize_git_path_component(input: &str) -> String {
     let mut out = String::with_capacity(input.len());
     for ch in input.chars() {
@@ -80,197 +97,6 @@ fn sanitize_git_path_component(input: &str) -> String {
     }
 }
 
-fn compress_image_to_jpeg_under_limit(bytes: &[u8], max_bytes: usize) -> Result<Vec<u8>, String> {
-    use image::imageops::FilterType;
-
-    let mut img = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
-
-    // Ensure largest dimension is within a reasonable bound.
-    let (w, h) = img.dimensions();
-    let max_dim: u32 = 1920;
-    let largest = w.max(h);
-    if largest > max_dim {
-        let scale = max_dim as f32 / largest as f32;
-        let nw = ((w as f32) * scale).round().max(1.0) as u32;
-        let nh = ((h as f32) * scale).round().max(1.0) as u32;
-        img = img.resize(nw, nh, FilterType::Lanczos3);
-    }
-
-    // Drop alpha by converting to RGB.
-    let img = image::DynamicImage::ImageRgb8(img.to_rgb8());
-
-    // Try decreasing quality.
-    for quality in [85u8, 80, 75, 70, 65, 60, 55, 50, 45] {
-        let mut out = Vec::new();
-        let mut enc = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
-        enc.encode_image(&img).map_err(|e| e.to_string())?;
-        if out.len() <= max_bytes {
-            return Ok(out);
-        }
-    }
-
-    Err("unable to compress image under size limit".to_string())
-}
-
-#[derive(Serialize)]
-struct GitHubPutContentReq {
-    message: String,
-    content: String,
-    branch: String,
-}
-
-#[derive(Deserialize)]
-struct GitHubPutContentResp {
-    content: GitHubContentInfo,
-}
-
-#[derive(Deserialize)]
-struct GitHubContentInfo {
-    path: String,
-}
-
-#[derive(Deserialize)]
-struct GitHubRepoResp {
-    permissions: Option<GitHubRepoPermissions>,
-}
-
-#[derive(Deserialize)]
-struct GitHubRepoPermissions {
-    push: Option<bool>,
-    admin: Option<bool>,
-}
-
-#[derive(Serialize)]
-struct GitHubValidateRepoResult {
-    push: bool,
-    admin: bool,
-}
-
-#[tauri::command]
-fn github_validate_repo(repo: String, token: String) -> Result<GitHubValidateRepoResult, String> {
-    let (owner, name) = parse_github_repo(&repo)?;
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("Carbo Markdown Editor")
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let url = format!("https://api.github.com/repos/{}/{}", owner, name);
-    let resp = client
-        .get(&url)
-        .bearer_auth(token)
-        .header("X-GitHub-Api-Version", GITHUB_API_VERSION)
-        .send()
-        .map_err(|e| e.to_string())?;
-
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let body = resp.text().unwrap_or_default();
-        return Err(format!("github validate failed: {} {}", status, body));
-    }
-
-    let parsed: GitHubRepoResp = resp.json().map_err(|e| e.to_string())?;
-    let push = parsed
-        .permissions
-        .as_ref()
-        .and_then(|p| p.push)
-        .unwrap_or(false);
-    let admin = parsed
-        .permissions
-        .as_ref()
-        .and_then(|p| p.admin)
-        .unwrap_or(false);
-
-    Ok(GitHubValidateRepoResult { push, admin })
-}
-
-#[tauri::command]
-fn github_upload_image_from_path(
-    repo: String,
-    branch: String,
-    path_prefix: String,
-    token: String,
-    local_path: String,
-    max_bytes: u64,
-) -> Result<String, String> {
-    let (owner, name) = parse_github_repo(&repo)?;
-
-    let src = std::path::Path::new(&local_path);
-    let meta = std::fs::metadata(src).map_err(|e| e.to_string())?;
-    if !meta.is_file() {
-        return Err("not a file".to_string());
-    }
-
-    let original = std::fs::read(src).map_err(|e| e.to_string())?;
-    let target = max_bytes as usize;
-
-    let (bytes, ext) = if (original.len() as u64) <= max_bytes {
-        let ext = src
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("bin")
-            .to_ascii_lowercase();
-        (original, ext)
-    } else {
-        // Best effort: compress to JPEG under the given limit.
-        let compressed = compress_image_to_jpeg_under_limit(&original, target)?;
-        (compressed, "jpg".to_string())
-    };
-
-    let file_stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
-    let file_stem = sanitize_git_path_component(file_stem);
-
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis();
-
-    let prefix = path_prefix.trim().trim_matches('/');
-    let prefix = if prefix.is_empty() { "images" } else { prefix };
-    let dst_path = format!("{}/{}-{}.{}", prefix, ts, file_stem, ext);
-
-    let content_base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
-    let branch = branch.trim();
-    let branch = if branch.is_empty() { "master" } else { branch };
-
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("Carbo Markdown Editor")
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let url = format!(
-        "https://api.github.com/repos/{}/{}/contents/{}",
-        owner, name, dst_path
-    );
-
-    let req_body = GitHubPutContentReq {
-        message: format!("chore(images): add {}", dst_path),
-        content: content_base64,
-        branch: branch.to_string(),
-    };
-
-    let resp = client
-        .put(&url)
-        .bearer_auth(token)
-        .header("X-GitHub-Api-Version", GITHUB_API_VERSION)
-        .json(&req_body)
-        .send()
-        .map_err(|e| e.to_string())?;
-
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let body = resp.text().unwrap_or_default();
-        return Err(format!("github upload failed: {} {}", status, body));
-    }
-
-    let parsed: GitHubPutContentResp = resp.json().map_err(|e| e.to_string())?;
-    let raw_url = format!(
-        "https://raw.githubusercontent.com/{}/{}/{}/{}",
-        owner, name, branch, parsed.content.path
-    );
-
-    Ok(raw_url)
-}
-
 fn is_allowed_text_extension(path: &std::path::Path) -> bool {
     let ext = path
         .extension()
@@ -336,84 +162,111 @@ fn list_text_files_in_dir(path: String) -> Result<Vec<FileTreeEntry>, String> {
 }
 
 #[tauri::command]
-fn copy_image_for_document(source_path: String, document_path: String) -> Result<String, String> {
+fn copy_image_for_document(
+    source_path: String,
+    document_path: String,
+    layout: Option<assets::AssetLayout>,
+    pipeline: Option<image_pipeline::ImagePipelineOptions>,
+    strip_metadata: Option<bool>,
+) -> Result<String, String> {
     let src = std::path::PathBuf::from(source_path);
     if !is_allowed_image_extension(&src) {
         return Err("unsupported image type".to_string());
     }
+    let meta = std::fs::metadata(&src).map_err(|e| e.to_string())?;
+    if !meta.is_file() {
+        return Err("not a file".to_string());
+    }
+    // Prevent loading extremely large files into memory.
+    if meta.len() > MAX_IMAGE_BYTES {
+        return Err("file too large".to_string());
+    }
+    let layout = layout.unwrap_or_default();
     let doc = std::path::PathBuf::from(document_path);
-    let doc_dir = doc.parent().ok_or_else(|| "document has no parent directory".to_string())?;
-    let doc_stem = doc
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .filter(|s| !s.is_empty())
-        .unwrap_or("document");
-    let assets_dir = doc_dir.join(format!("{}.assets", sanitize_git_path_component(doc_stem)));
-    std::fs::create_dir_all(&assets_dir).map_err(|e| e.to_string())?;
 
-    let extension = src
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("png")
-        .to_ascii_lowercase();
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis();
+    let bytes = std::fs::read(&src).map_err(|e| e.to_string())?;
+    let (bytes, extension) = image_type::validate_image(bytes, Some(file_extension(&src)))?;
+    let bytes = strip_image_metadata_if(
+        bytes,
+        workspace_settings::resolve_strip_metadata(strip_metadata, Some(&doc)),
+    )?;
+    let (bytes, extension) = apply_image_pipeline(bytes, extension, pipeline.as_ref())?;
     let source_stem = src
         .file_stem()
         .and_then(|s| s.to_str())
-        .filter(|s| !s.is_empty())
-        .unwrap_or(doc_stem);
-    let filename = format!(
-        "{}-{}-{}.{}",
-        ts,
-        sanitize_git_path_component(doc_stem),
-        sanitize_git_path_component(source_stem),
-        extension
-    );
-    let destination = assets_dir.join(filename);
-    std::fs::copy(&src, &destination).map_err(|e| e.to_string())?;
-    Ok(destination.to_string_lossy().to_string())
+        .filter(|s| !s.is_empty());
+    let destination =
+        assets::resolve_asset_destination(&layout, &doc, source_stem, &extension, &bytes)?;
+    if !destination.existing {
+        std::fs::write(&destination.path, bytes).map_err(|e| e.to_string())?;
+    }
+    Ok(assets::asset_output_path(&layout, &doc, &destination.path))
 }
 
 #[tauri::command]
-fn save_image_for_document(file_name: String, bytes: Vec<u8>, document_path: String) -> Result<String, String> {
+fn save_image_for_document(
+    file_name: String,
+    bytes: Vec<u8>,
+    document_path: String,
+    layout: Option<assets::AssetLayout>,
+    pipeline: Option<image_pipeline::ImagePipelineOptions>,
+    strip_metadata: Option<bool>,
+) -> Result<String, String> {
+    let layout = layout.unwrap_or_default();
     let doc = std::path::PathBuf::from(document_path);
-    let doc_dir = doc.parent().ok_or_else(|| "document has no parent directory".to_string())?;
-    let doc_stem = doc
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .filter(|s| !s.is_empty())
-        .unwrap_or("document");
-    let assets_dir = doc_dir.join(format!("{}.assets", sanitize_git_path_component(doc_stem)));
-    std::fs::create_dir_all(&assets_dir).map_err(|e| e.to_string())?;
+    let (bytes, extension) = image_type::validate_image(bytes, claimed_extension(&file_name))?;
+    let bytes = strip_image_metadata_if(
+        bytes,
+        workspace_settings::resolve_strip_metadata(strip_metadata, Some(&doc)),
+    )?;
 
     let source_name = sanitize_file_name(&file_name);
     let source_stem = std::path::Path::new(&source_name)
         .file_stem()
         .and_then(|s| s.to_str())
-        .filter(|s| !s.is_empty())
-        .unwrap_or(doc_stem);
-    let extension = std::path::Path::new(&source_name)
+        .filter(|s| !s.is_empty());
+    let (bytes, extension) = apply_image_pipeline(bytes, extension, pipeline.as_ref())?;
+    let destination =
+        assets::resolve_asset_destination(&layout, &doc, source_stem, &extension, &bytes)?;
+    if !destination.existing {
+        std::fs::write(&destination.path, bytes).map_err(|e| e.to_string())?;
+    }
+    Ok(assets::asset_output_path(&layout, &doc, &destination.path))
+}
+
+fn file_extension(path: &std::path::Path) -> &str {
+    path.extension().and_then(|s| s.to_str()).unwrap_or_default()
+}
+
+// Image extension of a file name sent by the webview. Names without one (such
+// as clipboard pastes, or "Screenshot 10.42") leave the type to the content.
+fn claimed_extension(file_name: &str) -> Option<&str> {
+    std::path::Path::new(file_name)
         .extension()
         .and_then(|s| s.to_str())
-        .unwrap_or("png")
-        .to_ascii_lowercase();
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis();
-    let filename = format!(
-        "{}-{}-{}.{}",
-        ts,
-        sanitize_git_path_component(doc_stem),
-        sanitize_git_path_component(source_stem),
-        extension
-    );
-    let destination = assets_dir.join(filename);
-    std::fs::write(&destination, bytes).map_err(|e| e.to_string())?;
-    Ok(destination.to_string_lossy().to_string())
+        .filter(|s| image_type::ImageType::from_extension(s).is_some())
+}
+
+fn strip_image_metadata_if(bytes: Vec<u8>, strip: bool) -> Result<Vec<u8>, String> {
+    if strip {
+        image_metadata::sanitize_image_metadata(&bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+fn apply_image_pipeline(
+    bytes: Vec<u8>,
+    extension: String,
+    pipeline: Option<&image_pipeline::ImagePipelineOptions>,
+) -> Result<(Vec<u8>, String), String> {
+    match pipeline {
+        Some(options) => {
+            let processed = image_pipeline::process_image(&bytes, &extension, options)?;
+            Ok((processed.bytes, processed.extension))
+        }
+        None => Ok((bytes, extension)),
+    }
 }
 
 fn sanitize_file_name(input: &str) -> String {
@@ -520,7 +373,7 @@ fn is_allowed_image_extension(path: &std::path::Path) -> bool {
         .to_ascii_lowercase();
     matches!(
         ext.as_str(),
-        "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg" | "bmp" | "ico"
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg" | "bmp" | "ico"
     )
 }
 
@@ -529,28 +382,37 @@ fn save_image_bytes(
     app: tauri::AppHandle,
     file_name: String,
     bytes: Vec<u8>,
+    naming: Option<assets::AssetNaming>,
+    strip_metadata: Option<bool>,
 ) -> Result<String, String> {
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis();
-
-    let file_name = sanitize_file_name(&file_name);
+    let (bytes, extension) = image_type::validate_image(bytes, claimed_extension(&file_name))?;
+    let file_name = std::path::Path::new(&sanitize_file_name(&file_name))
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string();
+    let bytes = strip_image_metadata_if(bytes, strip_metadata.unwrap_or(false))?;
 
     let dir = app
         .path()
         .resolve("carbo-assets/images", BaseDirectory::AppData)
         .map_err(|e| e.to_string())?;
-    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
 
-    let path = dir.join(format!("{}-{}", ts, file_name));
-    std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+    let destination =
+        assets::resolve_store_destination(&dir, &file_name, naming.unwrap_or_default(), &bytes)?;
+    if !destination.existing {
+        std::fs::write(&destination.path, bytes).map_err(|e| e.to_string())?;
+    }
 
-    Ok(path.to_string_lossy().to_string())
+    Ok(destination.path.to_string_lossy().to_string())
 }
 
 #[tauri::command]
-fn copy_image_to_app_data(app: tauri::AppHandle, path: String) -> Result<String, String> {
+fn copy_image_to_app_data(
+    app: tauri::AppHandle,
+    path: String,
+    naming: Option<assets::AssetNaming>,
+    strip_metadata: Option<bool>,
+) -> Result<String, String> {
     let src = std::path::Path::new(&path);
     if !is_allowed_image_extension(src) {
         return Err("unsupported image type".to_string());
@@ -562,31 +424,32 @@ fn copy_image_to_app_data(app: tauri::AppHandle, path: String) -> Result<String,
     }
 
     // Prevent loading extremely large files into memory.
-    const MAX_BYTES: u64 = 20 * 1024 * 1024;
-    if meta.len() > MAX_BYTES {
+    if meta.len() > MAX_IMAGE_BYTES {
         return Err("file too large".to_string());
     }
 
     let bytes = std::fs::read(src).map_err(|e| e.to_string())?;
-
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis();
+    let (bytes, extension) = image_type::validate_image(bytes, Some(file_extension(src)))?;
+    let bytes = strip_image_metadata_if(bytes, strip_metadata.unwrap_or(false))?;
 
     let file_name = src.file_name().and_then(|s| s.to_str()).unwrap_or("image");
-    let file_name = sanitize_file_name(file_name);
+    let file_name = std::path::Path::new(&sanitize_file_name(file_name))
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string();
 
     let dir = app
         .path()
         .resolve("carbo-assets/images", BaseDirectory::AppData)
         .map_err(|e| e.to_string())?;
-    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
 
-    let dst = dir.join(format!("{}-{}", ts, file_name));
-    std::fs::write(&dst, bytes).map_err(|e| e.to_string())?;
+    let dst =
+        assets::resolve_store_destination(&dir, &file_name, naming.unwrap_or_default(), &bytes)?;
+    if !dst.existing {
+        std::fs::write(&dst.path, bytes).map_err(|e| e.to_string())?;
+    }
 
-    Ok(dst.to_string_lossy().to_string())
+    Ok(dst.path.to_string_lossy().to_string())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -595,6 +458,7 @@ pub fn run() {
 
     tauri::Builder::default()
         .manage(StartupOpenPaths(Mutex::new(startup_open_paths)))
+        .manage(upload_tasks::UploadRegistry::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
@@ -608,20 +472,58 @@ pub fn run() {
                 let _ = window.emit("carbo-open-paths", OpenPathsPayload { paths });
             }
         }))
+        .register_asynchronous_uri_scheme_protocol(
+            remote_images::SCHEME,
+            |ctx, request, responder| {
+                let app = ctx.app_handle().clone();
+                tauri::async_runtime::spawn_blocking(move || {
+                    responder.respond(remote_images::respond(&app, &request))
+                });
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             save_export_bytes,
             save_image_bytes,
             copy_image_to_app_data,
-            github_validate_repo,
-            github_upload_image_from_path,
+            github_host::github_validate_repo,
+            github_host::github_rate_limit,
+            github_host::github_upload_image_from_path,
+            github_batch::github_upload_images_batch,
             read_text_file,
             write_text_file,
             list_text_files_in_dir,
             copy_image_for_document,
             save_image_for_document,
-            consume_startup_open_paths
+            consume_startup_open_paths,
+            workspace_settings::read_workspace_settings,
+            workspace_settings::write_workspace_settings,
+            gallery::list_image_assets,
+            image_edit::edit_image_asset,
+            localize::localize_remote_images,
+            url_templates::rewrite_image_links,
+            batch_upload::upload_document_images,
+            image_host::validate_image_host,
+            image_host::upload_image_to_host,
+            image_host::delete_from_image_host,
+            upload_tasks::cancel_image_upload,
+            credentials::set_host_token,
+            credentials::clear_host_token,
+            credentials::has_host_token,
+            upload_history::list_upload_history,
+            upload_history::delete_uploaded_image,
+            upload_queue::queue_image_upload,
+            upload_queue::list_queued_uploads,
+            upload_queue::retry_queued_uploads,
+            upload_queue::remove_queued_upload,
+            command_host::read_upload_commands,
+            command_host::write_upload_command,
+            http_client::read_network_settings,
+            http_client::write_network_settings,
+            remote_images::fetch_remote_image
         ])
         .setup(|app| {
+            upload_queue::start(app.handle())?;
+
             // Set window icon
             if let Some(window) = app.get_webview_window("main") {
                 // Load and decode icon PNG
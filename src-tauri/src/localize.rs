@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::http_client::NetworkSettings;
+use crate::{assets, image_host, image_type, markdown_images, workspace_settings};
+
+const DEFAULT_MAX_BYTES: u64 = 20 * 1024 * 1024;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct LocalizeOptions {
+    /// Largest accepted download.
+    pub max_bytes: u64,
+    /// Per-request timeout.
+    pub timeout_secs: u64,
+}
+
+impl Default for LocalizeOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalizedImage {
+    url: String,
+    path: String,
+    /// Document-relative link that replaced `url`.
+    link: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalizeFailure {
+    url: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalizeResult {
+    /// Document text with every localized URL rewritten.
+    text: String,
+    localized: Vec<LocalizedImage>,
+    failures: Vec<LocalizeFailure>,
+}
+
+/// Downloads every remote image referenced by the document into its asset
+/// folder and returns the rewritten text. `text` defaults to the file on disk,
+/// so unsaved editor content can be passed in. The document itself is not written.
+#[tauri::command]
+pub(crate) async fn localize_remote_images(
+    app: tauri::AppHandle,
+    document_path: String,
+    text: Option<String>,
+    layout: Option<assets::AssetLayout>,
+    options: Option<LocalizeOptions>,
+) -> Result<LocalizeResult, String> {
+    image_host::run_blocking(move || {
+        let document = PathBuf::from(document_path);
+        let text = match text {
+            Some(text) => text,
+            None => std::fs::read_to_string(&document).map_err(|e| e.to_string())?,
+        };
+        let options = options.unwrap_or_default();
+        let client =
+            NetworkSettings::load(&app).client(Duration::from_secs(options.timeout_secs.max(1)))?;
+
+        localize_text(
+            &client,
+            &document,
+            &text,
+            &layout.unwrap_or_default(),
+            &options,
+        )
+    })
+    .await
+}
+
+fn localize_text(
+    client: &reqwest::blocking::Client,
+    document: &Path,
+    text: &str,
+    layout: &assets::AssetLayout,
+    options: &LocalizeOptions,
+) -> Result<LocalizeResult, String> {
+    let strip = workspace_settings::resolve_strip_metadata(None, Some(document));
+    let references: Vec<_> = markdown_images::find_image_references(text)
+        .into_iter()
+        .filter(|r| markdown_images::is_remote_url(&r.url))
+        .collect();
+
+    // Each URL is downloaded once, however often it is referenced.
+    let mut links: HashMap<String, Result<String, String>> = HashMap::new();
+    let mut localized = Vec::new();
+    let mut failures = Vec::new();
+    for reference in &references {
+        if links.contains_key(&reference.url) {
+            continue;
+        }
+        let outcome = download_image(client, &reference.url, options)
+            .and_then(|bytes| image_type::validate_image(bytes, None))
+            .and_then(|(bytes, extension)| {
+                let bytes = crate::strip_image_metadata_if(bytes, strip)?;
+                let stem = url_file_stem(&reference.url);
+                let destination = assets::resolve_asset_destination(
+                    layout,
+                    document,
+                    stem.as_deref(),
+                    &extension,
+                    &bytes,
+                )?;
+                if !destination.existing {
+                    std::fs::write(&destination.path, &bytes).map_err(|e| e.to_string())?;
+                }
+                Ok(destination.path)
+            });
+        match outcome {
+            Ok(path) => {
+                let link_layout = assets::AssetLayout {
+                    relative_link: true,
+                    ..layout.clone()
+                };
+                let link = assets::asset_output_path(&link_layout, document, &path);
+                localized.push(LocalizedImage {
+                    url: reference.url.clone(),
+                    path: path.to_string_lossy().to_string(),
+                    link: link.clone(),
+                });
+                links.insert(reference.url.clone(), Ok(link));
+            }
+            Err(error) => {
+                failures.push(LocalizeFailure {
+                    url: reference.url.clone(),
+                    error: error.clone(),
+                });
+                links.insert(reference.url.clone(), Err(error));
+            }
+        }
+    }
+
+    let mut out = text.to_string();
+    for reference in references.iter().rev() {
+        if let Some(Ok(link)) = links.get(&reference.url) {
+            out.replace_range(reference.start..reference.end, link);
+        }
+    }
+
+    Ok(LocalizeResult {
+        text: out,
+        localized,
+        failures,
+    })
+}
+
+fn download_image(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    options: &LocalizeOptions,
+) -> Result<Vec<u8>, String> {
+    let resp = client.get(url).send().map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("download failed: {}", resp.status()));
+    }
+
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    // Some hosts serve images as octet-stream; content sniffing decides then.
+    if !content_type.is_empty()
+        && !content_type.starts_with("image/")
+        && !content_type.starts_with("application/octet-stream")
+    {
+        return Err(format!("unexpected content type: {}", content_type));
+    }
+    read_capped(resp, options.max_bytes)
+}
+
+/// Reads a response body, failing once it exceeds `max_bytes` instead of
+/// buffering whatever the server sends.
+pub(crate) fn read_capped(
+    resp: reqwest::blocking::Response,
+    max_bytes: u64,
+) -> Result<Vec<u8>, String> {
+    if resp.content_length().is_some_and(|len| len > max_bytes) {
+        return Err("image too large".to_string());
+    }
+    let mut bytes = Vec::new();
+    resp.take(max_bytes + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > max_bytes {
+        return Err("image too large".to_string());
+    }
+    Ok(bytes)
+}
+
+// Last path segment of the URL without its extension.
+fn url_file_stem(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let after_host = path.splitn(4, '/').nth(3)?;
+    let segment = after_host.rsplit('/').find(|s| !s.is_empty())?;
+    let stem = Path::new(segment).file_stem()?.to_str()?;
+    Some(stem.to_string()).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F,
+        0x15, 0xC4, 0x89, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0xF8,
+        0xCF, 0xC0, 0xF0, 0x1F, 0x00, 0x05, 0x00, 0x01, 0xFF, 0x89, 0x99, 0x3D, 0x1D, 0x00, 0x00,
+        0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    /// Serves a fixed set of routes on localhost and returns the base URL.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or("/");
+                let (head, body): (String, Vec<u8>) = match path {
+                    "/pic.png" => (
+                        format!(
+                            "200 OK\r\nContent-Type: image/png\r\nContent-Length: {}",
+                            PNG.len()
+                        ),
+                        PNG.to_vec(),
+                    ),
+                    "/octet" => (
+                        "200 OK\r\nContent-Type: application/octet-stream".to_string(),
+                        PNG.to_vec(),
+                    ),
+                    "/moved" => ("302 Found\r\nLocation: /pic.png".to_string(), Vec::new()),
+                    "/page.png" => (
+                        "200 OK\r\nContent-Type: text/html".to_string(),
+                        b"<p>hi</p>".to_vec(),
+                    ),
+                    "/declared-large" => (
+                        "200 OK\r\nContent-Type: image/png\r\nContent-Length: 1000000".to_string(),
+                        Vec::new(),
+                    ),
+                    // No Content-Length: only the capped read can catch it.
+                    "/streamed-large" => (
+                        "200 OK\r\nContent-Type: image/png".to_string(),
+                        vec![0; 4096],
+                    ),
+                    _ => ("404 Not Found".to_string(), Vec::new()),
+                };
+                let _ = write!(stream, "HTTP/1.1 {}\r\nConnection: close\r\n\r\n", head);
+                let _ = stream.write_all(&body);
+            }
+        });
+        base
+    }
+
+    fn client() -> reqwest::blocking::Client {
+        reqwest::blocking::Client::builder()
+            .no_proxy()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap()
+    }
+
+    fn options(max_bytes: u64) -> LocalizeOptions {
+        LocalizeOptions {
+            max_bytes,
+            ..LocalizeOptions::default()
+        }
+    }
+
+    fn temp_document(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("carbo-localize-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("note.md")
+    }
+
+    #[test]
+    fn rejects_non_image_content_types() {
+        let base = serve();
+        let error =
+            download_image(&client(), &format!("{}/page.png", base), &options(1024)).unwrap_err();
+        assert!(
+            error.starts_with("unexpected content type: text/html"),
+            "{}",
+            error
+        );
+        let bytes = download_image(&client(), &format!("{}/octet", base), &options(1024)).unwrap();
+        assert_eq!(bytes, PNG);
+    }
+
+    #[test]
+    fn enforces_the_size_cap() {
+        let base = serve();
+        for path in ["/declared-large", "/streamed-large"] {
+            let error = download_image(&client(), &format!("{}{}", base, path), &options(1024))
+                .unwrap_err();
+            assert_eq!(error, "image too large", "{}", path);
+        }
+        let exact = options(PNG.len() as u64);
+        assert!(download_image(&client(), &format!("{}/pic.png", base), &exact).is_ok());
+    }
+
+    #[test]
+    fn follows_redirects() {
+        let base = serve();
+        let bytes = download_image(&client(), &format!("{}/moved", base), &options(1024)).unwrap();
+        assert_eq!(bytes, PNG);
+    }
+
+    #[test]
+    fn rewrites_localized_links_and_reports_failures() {
+        let base = serve();
+        let document = temp_document("rewrite");
+        let text = format!(
+            "![a]({base}/pic.png) and <img src=\"{base}/pic.png\">\n![b]({base}/page.png) ![c]({base}/missing.png) ![d](local.png)",
+            base = base
+        );
+        let result = localize_text(
+            &client(),
+            &document,
+            &text,
+            &assets::AssetLayout::default(),
+            &options(1024),
+        )
+        .unwrap();
+
+        // Downloaded once, both references rewritten to the same relative link.
+        assert_eq!(result.localized.len(), 1);
+        let link = &result.localized[0].link;
+        assert!(
+            link.starts_with("note.assets/") && link.ends_with(".png"),
+            "{}",
+            link
+        );
+        assert!(Path::new(&result.localized[0].path).is_file());
+        assert_eq!(result.text.matches(link.as_str()).count(), 2);
+        assert!(!result.text.contains(&format!("{}/pic.png", base)));
+
+        // Failed downloads and local images keep their links.
+        let failed: Vec<_> = result.failures.iter().map(|f| f.url.as_str()).collect();
+        assert_eq!(
+            failed,
+            [
+                format!("{}/page.png", base),
+                format!("{}/missing.png", base)
+            ]
+        );
+        assert!(result.text.contains(&format!("![b]({}/page.png)", base)));
+        assert!(result.text.contains(&format!("![c]({}/missing.png)", base)));
+        assert!(result.text.contains("![d](local.png)"));
+    }
+}
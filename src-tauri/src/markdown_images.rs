@@ -0,0 +1,218 @@
+use std::path::{Path, PathBuf};
+
+/// An image reference in Markdown text. `start..end` is the byte range of the
+/// link target, so callers can rewrite it in place.
+pub(crate) struct ImageRef {
+    pub url: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Finds inline Markdown images (`![alt](url "title")`, `![alt](<url>)`) and
+/// HTML `<img src="...">` tags, in document order.
+pub(crate) fn find_image_references(text: &str) -> Vec<ImageRef> {
+    let mut refs = Vec::new();
+    collect_markdown_images(text, &mut refs);
+    collect_html_images(text, &mut refs);
+    refs.sort_by_key(|r| r.start);
+    refs
+}
+
+fn collect_markdown_images(text: &str, refs: &mut Vec<ImageRef>) {
+    let bytes = text.as_bytes();
+    let mut search = 0;
+    while let Some(found) = text[search..].find("![") {
+        let alt_start = search + found + 2;
+        search = alt_start;
+
+        // Matching `]`, allowing nested brackets in the alt text.
+        let mut depth = 1;
+        let mut pos = alt_start;
+        while pos < bytes.len() && depth > 0 {
+            match bytes[pos] {
+                b'\\' => pos += 1,
+                b'[' => depth += 1,
+                b']' => depth -= 1,
+                b'\n' if bytes.get(pos + 1) == Some(&b'\n') => break,
+                _ => {}
+            }
+            pos += 1;
+        }
+        if depth != 0 || bytes.get(pos) != Some(&b'(') {
+            continue;
+        }
+        pos += 1;
+        while pos < bytes.len() && matches!(bytes[pos], b' ' | b'\t') {
+            pos += 1;
+        }
+
+        let (start, end) = if bytes.get(pos) == Some(&b'<') {
+            let Some(close) = text[pos + 1..].find('>') else {
+                continue;
+            };
+            (pos + 1, pos + 1 + close)
+        } else {
+            let start = pos;
+            let mut parens = 0;
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'(' => parens += 1,
+                    b')' if parens == 0 => break,
+                    b')' => parens -= 1,
+                    c if c.is_ascii_whitespace() => break,
+                    _ => {}
+                }
+                pos += 1;
+            }
+            (start, pos)
+        };
+        if start == end || text[start..end].contains('\n') {
+            continue;
+        }
+        refs.push(ImageRef {
+            url: text[start..end].to_string(),
+            start,
+            end,
+        });
+        search = end;
+    }
+}
+
+fn collect_html_images(text: &str, refs: &mut Vec<ImageRef>) {
+    let lower = text.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lower[search..].find("<img") {
+        let tag_start = search + found;
+        let tag_end = lower[tag_start..].find('>').map_or(text.len(), |e| tag_start + e);
+        search = tag_end;
+
+        let tag = &lower[tag_start..tag_end];
+        // `src=` preceded by whitespace, so `data-src=` is not mistaken for it.
+        let Some(src) = tag
+            .match_indices("src=")
+            .map(|(idx, _)| idx)
+            .find(|&idx| idx > 0 && tag.as_bytes()[idx - 1].is_ascii_whitespace())
+        else {
+            continue;
+        };
+        let value_start = tag_start + src + 4;
+        let (start, end) = match text.as_bytes().get(value_start) {
+            Some(quote @ (b'"' | b'\'')) => {
+                let Some(close) = text[value_start + 1..tag_end].find(*quote as char) else {
+                    continue;
+                };
+                (value_start + 1, value_start + 1 + close)
+            }
+            // Unquoted values run to whitespace or the end of the tag; `/`
+            // belongs to the value, as in `src=images/a.png`.
+            _ => {
+                let close = text[value_start..tag_end]
+                    .find(char::is_whitespace)
+                    .unwrap_or(tag_end - value_start);
+                (value_start, value_start + close)
+            }
+        };
+        if start < end {
+            refs.push(ImageRef {
+                url: text[start..end].to_string(),
+                start,
+                end,
+            });
+        }
+    }
+}
+
+/// True for `http://` and `https://` URLs.
+pub(crate) fn is_remote_url(url: &str) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Resolves a local image reference against the document's directory.
+/// Remote URLs, `data:` URIs and other schemes yield `None`.
+pub(crate) fn resolve_local_reference(url: &str, document: &Path) -> Option<PathBuf> {
+    let url = url.trim();
+    let without_fragment = url.split(['#', '?']).next().unwrap_or(url);
+    let decoded = percent_decode(without_fragment);
+
+    if let Some(path) = decoded.strip_prefix("file://") {
+        // `file:///C:/x` on Windows, `file:///x` elsewhere.
+        let path = if path.len() > 2 && path.as_bytes()[2] == b':' {
+            &path[1..]
+        } else {
+            path
+        };
+        return Some(PathBuf::from(path));
+    }
+    if decoded.is_empty() || has_scheme(&decoded) {
+        return None;
+    }
+
+    let path = Path::new(&decoded);
+    if path.is_absolute() {
+        return Some(path.to_path_buf());
+    }
+    document.parent().map(|dir| dir.join(path))
+}
+
+// `c:/x` is a Windows drive path, not a scheme.
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(idx) if idx > 1 => url[..idx]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')),
+        _ => false,
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            if let Ok(b) = u8::from_str_radix(&input[i + 1..i + 3], 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<String> {
+        find_image_references(text)
+            .into_iter()
+            .map(|r| r.url)
+            .collect()
+    }
+
+    #[test]
+    fn reads_unquoted_html_src_up_to_whitespace_or_tag_end() {
+        assert_eq!(urls("<img src=images/a.png>"), ["images/a.png"]);
+        assert_eq!(
+            urls("<img alt=x src=https://x.example/a.png width=10>"),
+            ["https://x.example/a.png"]
+        );
+        assert_eq!(urls("<IMG SRC=../b.gif\n/>"), ["../b.gif"]);
+        assert_eq!(
+            urls(r#"<img src="a b.png"> <img data-src=c.png src='d.png'>"#),
+            ["a b.png", "d.png"]
+        );
+
+        let text = "x <img src=images/a.png> y";
+        let found = &find_image_references(text)[0];
+        assert_eq!(&text[found.start..found.end], "images/a.png");
+    }
+}
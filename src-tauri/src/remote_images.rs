@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::http::{header, Request, Response};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::image_host::{self, HostError, HostErrorKind};
+use crate::{credentials, github_host, http_client, image_type, localize};
+
+/// `carbo-remote://localhost/<url without https://>` serves the image at
+/// that URL (on Windows, `http://carbo-remote.localhost/...`).
+pub(crate) const SCHEME: &str = "carbo-remote";
+const REMOTE_CACHE_DIR: &str = "carbo-assets/remote-cache";
+/// Cached images younger than this are served without asking the server.
+const FRESH_FOR: Duration = Duration::from_secs(5 * 60);
+/// Largest image downloaded.
+const MAX_BYTES: u64 = 20 * 1024 * 1024;
+/// Cached images not checked for this long are removed.
+const MAX_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Beyond this, the least recently checked images are removed.
+const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedMeta {
+    url: String,
+    etag: Option<String>,
+    mime_type: String,
+    /// Unix seconds of the last download or revalidation.
+    checked_at: u64,
+}
+
+struct RemoteImage {
+    bytes: Vec<u8>,
+    mime_type: String,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn cache_paths(app: &tauri::AppHandle, url: &str) -> Result<(PathBuf, PathBuf), String> {
+    let dir = app
+        .path()
+        .resolve(REMOTE_CACHE_DIR, BaseDirectory::AppData)
+        .map_err(|e| e.to_string())?;
+    let name: String = Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((dir.join(&name), dir.join(format!("{}.json", name))))
+}
+
+fn read_cached(body: &Path, meta: &Path) -> Option<(CachedMeta, Vec<u8>)> {
+    let meta: CachedMeta = serde_json::from_slice(&std::fs::read(meta).ok()?).ok()?;
+    Some((meta, std::fs::read(body).ok()?))
+}
+
+fn write_meta(path: &Path, meta: &CachedMeta) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(meta).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Removes cached images older than `max_age`, then the least recently
+/// checked ones until the rest fit in `max_bytes`. Age is taken from the
+/// metadata file, which is rewritten on every download and revalidation.
+fn prune_cache(dir: &Path, max_age: Duration, max_bytes: u64) -> std::io::Result<()> {
+    let now = SystemTime::now();
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let meta_path = entry?.path();
+        if meta_path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let body_path = meta_path.with_extension("");
+        let Ok(checked) = std::fs::metadata(&meta_path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        let size = [&meta_path, &body_path]
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum::<u64>();
+        entries.push((checked, size, meta_path, body_path));
+    }
+
+    // Newest first: whatever no longer fits is the oldest.
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+    let mut kept = 0u64;
+    for (checked, size, meta_path, body_path) in entries {
+        let expired = now.duration_since(checked).is_ok_and(|age| age > max_age);
+        if !expired && kept + size <= max_bytes {
+            kept += size;
+            continue;
+        }
+        let _ = std::fs::remove_file(&body_path);
+        let _ = std::fs::remove_file(&meta_path);
+    }
+    Ok(())
+}
+
+/// Fetches an image from a GitHub repository with the stored token, through
+/// the disk cache. A stale copy is served when the server cannot be reached.
+fn fetch(app: &tauri::AppHandle, url: &str) -> Result<RemoteImage, HostError> {
+    let key = github_host::raw_url_credential_key(url).ok_or_else(|| {
+        HostError::new(
+            HostErrorKind::Other,
+            format!("{} is not a GitHub raw file URL", url),
+        )
+    })?;
+    let (body_path, meta_path) = cache_paths(app, url)?;
+    let cached = read_cached(&body_path, &meta_path);
+    if let Some((meta, bytes)) = &cached {
+        if unix_now().saturating_sub(meta.checked_at) < FRESH_FOR.as_secs() {
+            return Ok(RemoteImage {
+                bytes: bytes.clone(),
+                mime_type: meta.mime_type.clone(),
+            });
+        }
+    }
+
+    // Public repositories work without a token.
+    let token = credentials::stored_token(app, &key).ok().flatten();
+    let client = http_client::client(app, None)?;
+    let mut req = client.get(url);
+    if let Some(token) = &token {
+        req = req.bearer_auth(token);
+    }
+    if let Some(etag) = cached.as_ref().and_then(|(meta, _)| meta.etag.as_ref()) {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    let resp = match req.send() {
+        Ok(resp) => resp,
+        Err(e) => {
+            return match cached {
+                Some((meta, bytes)) => Ok(RemoteImage {
+                    bytes,
+                    mime_type: meta.mime_type,
+                }),
+                None => Err(HostError::network("remote image", e)),
+            }
+        }
+    };
+
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        if let Some((mut meta, bytes)) = cached {
+            meta.checked_at = unix_now();
+            let _ = write_meta(&meta_path, &meta);
+            return Ok(RemoteImage {
+                bytes,
+                mime_type: meta.mime_type,
+            });
+        }
+    }
+    if !resp.status().is_success() {
+        return Err(HostError::from_response("remote image", resp));
+    }
+
+    let etag = resp
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bytes = localize::read_capped(resp, MAX_BYTES)?;
+    // Never hand the webview anything but a sanitized image.
+    let (bytes, extension) = image_type::validate_image(bytes, None)?;
+    let mime_type = image_type::ImageType::from_extension(&extension)
+        .map_or("application/octet-stream", |kind| kind.mime_type())
+        .to_string();
+
+    if let Some(dir) = body_path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&body_path, &bytes).map_err(|e| e.to_string())?;
+    write_meta(
+        &meta_path,
+        &CachedMeta {
+            url: url.to_string(),
+            etag,
+            mime_type: mime_type.clone(),
+            checked_at: unix_now(),
+        },
+    )?;
+    // The cache only grows here, so this is where it is kept in bounds.
+    if let Some(dir) = body_path.parent() {
+        let _ = prune_cache(dir, MAX_CACHE_AGE, MAX_CACHE_BYTES);
+    }
+    Ok(RemoteImage { bytes, mime_type })
+}
+
+/// The remote URL a `carbo-remote` request stands for.
+fn target_url(request: &Request<Vec<u8>>) -> Option<String> {
+    let uri = request.uri();
+    let rest = uri.path().trim_start_matches('/');
+    if rest.is_empty() {
+        return None;
+    }
+    Some(match uri.query() {
+        Some(query) => format!("https://{}?{}", rest, query),
+        None => format!("https://{}", rest),
+    })
+}
+
+/// Answers a `carbo-remote` request; runs on a blocking thread.
+pub(crate) fn respond(app: &tauri::AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let result = target_url(request)
+        .ok_or_else(|| HostError::new(HostErrorKind::Other, "no image URL in the request"))
+        .and_then(|url| fetch(app, &url));
+    let response = match result {
+        Ok(image) => Response::builder()
+            .header(header::CONTENT_TYPE, image.mime_type)
+            .header(header::CACHE_CONTROL, "max-age=300")
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(image.bytes),
+        Err(error) => {
+            let status = match error.kind {
+                HostErrorKind::Auth => StatusCode::FORBIDDEN,
+                HostErrorKind::NotFound => StatusCode::NOT_FOUND,
+                HostErrorKind::Other => StatusCode::BAD_REQUEST,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            Response::builder()
+                .status(status.as_u16())
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(error.message.into_bytes())
+        }
+    };
+    response.unwrap_or_else(|_| Response::new(Vec::new()))
+}
+
+/// Returns the image at a GitHub raw file URL as a `data:` URL, fetched like
+/// `carbo-remote` requests, so exports can embed images from private repositories.
+#[tauri::command]
+pub(crate) async fn fetch_remote_image(
+    app: tauri::AppHandle,
+    url: String,
+) -> Result<String, HostError> {
+    image_host::run_blocking(move || {
+        let image = fetch(&app, url.trim())?;
+        Ok(format!(
+            "data:{};base64,{}",
+            image.mime_type,
+            base64::engine::general_purpose::STANDARD.encode(image.bytes)
+        ))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached(dir: &Path, name: &str, size: usize, age: Duration) {
+        std::fs::write(dir.join(name), vec![0u8; size]).unwrap();
+        let meta = dir.join(format!("{}.json", name));
+        std::fs::write(&meta, b"{}").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&meta)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[test]
+    fn prunes_expired_then_oldest_images() {
+        let dir = std::env::temp_dir().join(format!("carbo-remote-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let hour = Duration::from_secs(60 * 60);
+        cached(&dir, "new", 100, Duration::ZERO);
+        cached(&dir, "recent", 100, hour);
+        cached(&dir, "older", 100, 2 * hour);
+        cached(&dir, "expired", 10, 48 * hour);
+
+        prune_cache(&dir, 24 * hour, 250).unwrap();
+        for (name, kept) in [
+            ("new", true),
+            ("recent", true),
+            ("older", false),
+            ("expired", false),
+        ] {
+            assert_eq!(dir.join(name).exists(), kept, "{}", name);
+            assert_eq!(
+                dir.join(format!("{}.json", name)).exists(),
+                kept,
+                "{}",
+                name
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
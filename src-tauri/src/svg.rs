@@ -0,0 +1,500 @@
+/// True when `bytes` look like an SVG document (optionally behind an XML
+/// declaration, doctype or comments).
+pub(crate) fn looks_like_svg(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return false;
+    };
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix("<?") {
+            let Some(end) = after.find("?>") else {
+                return false;
+            };
+            rest = after[end + 2..].trim_start();
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            let Some(end) = after.find("-->") else {
+                return false;
+            };
+            rest = after[end + 3..].trim_start();
+        } else if let Some(after) = rest.strip_prefix("<!") {
+            let Some(end) = after.find('>') else {
+                return false;
+            };
+            rest = after[end + 1..].trim_start();
+        } else {
+            return rest.starts_with("<svg");
+        }
+    }
+}
+
+/// Text-level SVG minification: drops comments, `<metadata>` blocks and
+/// whitespace-only text between tags, and collapses whitespace runs elsewhere.
+pub(crate) fn minify_svg(text: &str) -> String {
+    let without_comments = remove_delimited(text, "<!--", "-->");
+    let without_metadata = remove_delimited(&without_comments, "<metadata", "</metadata>");
+
+    let mut out = String::with_capacity(without_metadata.len());
+    let mut pending_space = false;
+    for ch in without_metadata.chars() {
+        if ch.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if pending_space {
+            // Whitespace-only text between two tags carries no meaning.
+            let between_tags = out.ends_with('>') && ch == '<';
+            if !out.is_empty() && !between_tags {
+                out.push(' ');
+            }
+            pending_space = false;
+        }
+        out.push(ch);
+    }
+    out
+}
+
+fn remove_delimited(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        match rest[start..].find(close) {
+            Some(end) => rest = &rest[start + end + close.len()..],
+            None => return out,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Elements removed together with their content.
+const BLOCKED_ELEMENTS: &[&str] = &[
+    "script",
+    "foreignobject",
+    "iframe",
+    "embed",
+    "object",
+    "audio",
+    "video",
+    "handler",
+    "listener",
+];
+
+/// Removes active content from an SVG: script-capable elements, event handler
+/// attributes, `javascript:` values, doctypes (entity expansion), processing
+/// instructions other than the XML declaration and any reference that is not
+/// a fragment (`#id`) or an inline raster `data:` URI.
+pub(crate) fn sanitize_svg(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    // Name and nesting depth of the blocked element being skipped.
+    let mut skipping: Option<(String, usize)> = None;
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            out.push_str(&rest[..start]);
+        }
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").unwrap_or(after.len());
+            if skipping.is_none() {
+                out.push_str("<![CDATA[");
+                out.push_str(&sanitize_css(&after[..end]));
+                out.push_str("]]>");
+            }
+            rest = after.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<!") {
+            rest = skip_declaration(rest);
+            continue;
+        }
+        if rest.starts_with("<?") {
+            let end = rest.find("?>").map_or(rest.len(), |e| e + 2);
+            // Only the XML declaration survives; other processing
+            // instructions such as `<?xml-stylesheet?>` load external files.
+            if out.trim_start_matches('\u{feff}').trim().is_empty()
+                && is_xml_declaration(&rest[..end])
+            {
+                out.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let Some(tag) = parse_tag(rest) else {
+            // Stray '<': escape it and move on.
+            if skipping.is_none() {
+                out.push_str("&lt;");
+            }
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[tag.len..];
+        let local = tag.local_name();
+
+        if let Some((name, depth)) = skipping.as_mut() {
+            if *name == local && !tag.self_closing {
+                if tag.closing {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        skipping = None;
+                    }
+                } else {
+                    *depth += 1;
+                }
+            }
+            continue;
+        }
+
+        if BLOCKED_ELEMENTS.contains(&local.as_str()) {
+            if !tag.closing && !tag.self_closing {
+                skipping = Some((local, 1));
+            }
+            continue;
+        }
+
+        tag.write_sanitized(&mut out);
+
+        if local == "style" && !tag.closing && !tag.self_closing {
+            let end = rest.find("</").unwrap_or(rest.len());
+            // CDATA sections are handled (and sanitized) by the main loop.
+            if !rest[..end].contains("<![CDATA[") {
+                out.push_str(&sanitize_css(&rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+    }
+    if skipping.is_none() {
+        out.push_str(rest);
+    }
+    out
+}
+
+// Skips a `<!DOCTYPE ...>` including an internal `[...]` subset.
+fn is_xml_declaration(instruction: &str) -> bool {
+    instruction
+        .strip_prefix("<?xml")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_whitespace()) || rest == "?>")
+}
+
+fn skip_declaration(rest: &str) -> &str {
+    let mut in_subset = false;
+    for (i, ch) in rest.char_indices() {
+        match ch {
+            '[' => in_subset = true,
+            ']' => in_subset = false,
+            '>' if !in_subset => return &rest[i + 1..],
+            _ => {}
+        }
+    }
+    ""
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attrs: Vec<(&'a str, Option<String>)>,
+    closing: bool,
+    self_closing: bool,
+    len: usize,
+}
+
+impl Tag<'_> {
+    fn local_name(&self) -> String {
+        let name = self.name.rsplit(':').next().unwrap_or(self.name);
+        name.to_ascii_lowercase()
+    }
+
+    fn write_sanitized(&self, out: &mut String) {
+        out.push('<');
+        if self.closing {
+            out.push('/');
+        }
+        out.push_str(self.name);
+        for (name, value) in &self.attrs {
+            let Some(value) = sanitize_attribute(name, value.as_deref()) else {
+                continue;
+            };
+            out.push(' ');
+            out.push_str(name);
+            if let Some(value) = value {
+                out.push_str("=\"");
+                out.push_str(
+                    &value
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('"', "&quot;"),
+                );
+                out.push('"');
+            }
+        }
+        if self.self_closing {
+            out.push('/');
+        }
+        out.push('>');
+    }
+}
+
+fn parse_tag(input: &str) -> Option<Tag<'_>> {
+    let bytes = input.as_bytes();
+    let mut pos = 1;
+    let closing = bytes.get(pos) == Some(&b'/');
+    if closing {
+        pos += 1;
+    }
+    let name_start = pos;
+    while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && !matches!(bytes[pos], b'>' | b'/') {
+        pos += 1;
+    }
+    if pos == name_start || !bytes[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let name = &input[name_start..pos];
+
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        match bytes.get(pos)? {
+            b'>' => {
+                pos += 1;
+                break;
+            }
+            b'/' => {
+                self_closing = true;
+                pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let attr_start = pos;
+        while pos < bytes.len()
+            && !bytes[pos].is_ascii_whitespace()
+            && !matches!(bytes[pos], b'=' | b'>' | b'/')
+        {
+            pos += 1;
+        }
+        let attr_name = &input[attr_start..pos];
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&b'=') {
+            attrs.push((attr_name, None));
+            continue;
+        }
+        pos += 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let value = match bytes.get(pos)? {
+            quote @ (b'"' | b'\'') => {
+                let end = input[pos + 1..].find(*quote as char)? + pos + 1;
+                let value = &input[pos + 1..end];
+                pos = end + 1;
+                value
+            }
+            _ => {
+                let value_start = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                &input[value_start..pos]
+            }
+        };
+        attrs.push((attr_name, Some(value.to_string())));
+    }
+
+    Some(Tag {
+        name,
+        attrs,
+        closing,
+        self_closing,
+        len: pos,
+    })
+}
+
+// `None` drops the attribute; `Some(value)` keeps it with its decoded, possibly
+// rewritten value.
+fn sanitize_attribute(name: &str, value: Option<&str>) -> Option<Option<String>> {
+    let lower = name.to_ascii_lowercase();
+    if lower.starts_with("on") {
+        return None;
+    }
+    let Some(value) = value else {
+        return Some(None);
+    };
+    // Checks run on the entity-decoded value so `&#106;avascript:` is caught too.
+    let decoded = decode_entities(value);
+    if is_script_url(&decoded) {
+        return None;
+    }
+    if lower == "href" || lower.ends_with(":href") || lower == "src" {
+        return is_safe_reference(&decoded).then_some(Some(decoded));
+    }
+    if lower == "style" || decoded.to_ascii_lowercase().contains("url(") {
+        return Some(Some(sanitize_css(&decoded)));
+    }
+    Some(Some(decoded))
+}
+
+fn decode_entities(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|&e| e <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        } else if let Some(dec) = entity.strip_prefix('#') {
+            dec.parse::<u32>().ok().and_then(char::from_u32)
+        } else {
+            match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "colon" => Some(':'),
+                "tab" => Some('\t'),
+                "newline" => Some('\n'),
+                _ => None,
+            }
+        };
+        match decoded {
+            Some(ch) => {
+                out.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_script_url(value: &str) -> bool {
+    let compact: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    compact.contains("javascript:") || compact.contains("vbscript:")
+}
+
+fn is_safe_reference(value: &str) -> bool {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return true;
+    }
+    let lower = value.to_ascii_lowercase();
+    lower.starts_with("data:image/") && !lower.starts_with("data:image/svg")
+}
+
+// Drops `@import` rules and neutralizes non-local `url(...)` references.
+fn sanitize_css(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let import = lower.find("@import");
+        let url = lower.find("url(");
+        match (import, url) {
+            (Some(i), u) if u.is_none_or(|u| i < u) => {
+                out.push_str(&rest[..i]);
+                let end = rest[i..].find(';').map_or(rest.len(), |e| i + e + 1);
+                rest = &rest[end..];
+            }
+            (_, Some(u)) => {
+                out.push_str(&rest[..u]);
+                let end = rest[u..].find(')').map_or(rest.len(), |e| u + e + 1);
+                let inner = rest[u + 4..end.saturating_sub(1).max(u + 4)]
+                    .trim()
+                    .trim_matches(|c| c == '"' || c == '\'');
+                if is_safe_reference(inner) {
+                    out.push_str(&rest[u..end]);
+                } else {
+                    out.push_str("none");
+                }
+                rest = &rest[end..];
+            }
+            _ => {
+                out.push_str(rest);
+                return out;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_a_leading_xml_declaration() {
+        let svg = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<?xml-stylesheet type=\"text/xsl\" href=\"http://evil.example/x.xsl\"?>",
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"><?php echo 1; ?><rect/></svg>",
+            "<?xml version=\"1.0\"?>"
+        );
+        assert_eq!(
+            sanitize_svg(svg),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"><rect/></svg>"
+        );
+        assert_eq!(
+            sanitize_svg("<?xml-stylesheet href=\"x.css\"?><svg/>"),
+            "<svg/>"
+        );
+    }
+
+    #[test]
+    fn drops_event_handler_attributes() {
+        let svg = r#"<svg onload="alert(1)"><rect ONCLICK='x()' onmouseover=y() width="1"/></svg>"#;
+        assert_eq!(sanitize_svg(svg), r#"<svg><rect width="1"/></svg>"#);
+    }
+
+    #[test]
+    fn drops_script_and_external_links() {
+        let svg = concat!(
+            r#"<svg><a href="javascript:alert(1)"><text>a</text></a>"#,
+            r#"<a xlink:href="&#106;avascript:alert(1)"/>"#,
+            r#"<image href="https://evil.example/x.png"/>"#,
+            r##"<use href="#shape"/><script>alert(1)</script></svg>"##
+        );
+        assert_eq!(
+            sanitize_svg(svg),
+            r##"<svg><a><text>a</text></a><a/><image/><use href="#shape"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn neutralizes_external_css_urls() {
+        let svg = concat!(
+            r#"<svg><style>@import "https://evil.example/a.css"; rect { fill: url(https://evil.example/p) }</style>"#,
+            r##"<rect style="fill: url('#grad')" filter="url(http://evil.example/f)"/></svg>"##
+        );
+        assert_eq!(
+            sanitize_svg(svg),
+            concat!(
+                r#"<svg><style> rect { fill: none }</style>"#,
+                r##"<rect style="fill: url('#grad')" filter="none"/></svg>"##
+            )
+        );
+    }
+}
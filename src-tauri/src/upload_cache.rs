@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::image_host::{self, HostError, ImageHost, UploadedFile};
+use crate::upload_tasks;
+
+const UPLOAD_CACHE_FILE: &str = "carbo-assets/upload-cache.json";
+
+/// Held while the cache file is read, merged and rewritten, so saves from
+/// different `UploadCache`s (the queue worker, a batch) cannot interleave.
+static CACHE_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+// The lock guards no data, so one poisoned by a panicking save is still usable.
+fn lock_cache_file() -> MutexGuard<'static, ()> {
+    CACHE_FILE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Replaces `path` with `bytes` through a temporary file and a rename, so a
+/// crash mid-write leaves the old file rather than a truncated one.
+pub(crate) fn write_file_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| format!("{} has no parent directory", path.display()))?;
+    std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    let temp = parent.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+    std::fs::write(&temp, bytes)
+        .and_then(|_| std::fs::rename(&temp, path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp);
+            e.to_string()
+        })
+}
+
+/// Git's object id for `bytes` stored as a file: SHA-1 of `blob <len>\0<bytes>`.
+pub(crate) fn git_blob_sha(bytes: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", bytes.len()).as_bytes());
+    hasher.update(bytes);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CachedUpload {
+    remote_path: String,
+    url: String,
+    /// Content hashes of the source files these bytes were made from, so a
+    /// re-upload can be recognised before the source is processed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<String>,
+}
+
+/// Target key -> blob sha -> uploaded file.
+type CacheEntries = HashMap<String, HashMap<String, CachedUpload>>;
+
+#[derive(Default)]
+struct CacheState {
+    /// The file as opened, plus everything recorded since.
+    entries: CacheEntries,
+    /// Uploads recorded since the cache was opened. Only these are written
+    /// back, so saving an older snapshot cannot undo a concurrent `forget`.
+    added: CacheEntries,
+    /// Source hashes seen for files that were already cached: (target, sha, source).
+    added_sources: Vec<(String, String, String)>,
+}
+
+/// Uploaded files by the git blob sha of the bytes that were sent, kept in
+/// `$APPDATA/carbo-assets/upload-cache.json`. This is the only record of what
+/// is already hosted, so `forget` covers every way of deleting an image.
+pub(crate) struct UploadCache {
+    path: PathBuf,
+    state: Mutex<CacheState>,
+}
+
+fn add_source(cached: &mut CachedUpload, source: &str) {
+    if !cached.sources.iter().any(|known| known == source) {
+        cached.sources.push(source.to_string());
+    }
+}
+
+impl UploadCache {
+    pub(crate) fn open(app: &tauri::AppHandle) -> Result<Self, String> {
+        let path = app
+            .path()
+            .resolve(UPLOAD_CACHE_FILE, BaseDirectory::AppData)
+            .map_err(|e| e.to_string())?;
+        Ok(Self::at(path))
+    }
+
+    fn at(path: PathBuf) -> Self {
+        let entries = {
+            let _file = lock_cache_file();
+            load_entries(&path)
+        };
+        Self {
+            state: Mutex::new(CacheState {
+                entries,
+                ..CacheState::default()
+            }),
+            path,
+        }
+    }
+
+    fn find(
+        &self,
+        host: &dyn ImageHost,
+        matches: impl Fn(&String, &CachedUpload) -> bool,
+    ) -> Option<UploadedFile> {
+        let state = self.state.lock().ok()?;
+        let (_, cached) = state
+            .entries
+            .get(&host.target_key())?
+            .iter()
+            .find(|(sha, cached)| matches(sha, cached))?;
+        // The URL is rebuilt from the remote path, so it follows the host's
+        // current URL template.
+        Some(UploadedFile {
+            url: host.public_url(&cached.remote_path),
+            remote_path: cached.remote_path.clone(),
+        })
+    }
+
+    pub(crate) fn get(&self, host: &dyn ImageHost, sha: &str) -> Option<UploadedFile> {
+        self.find(host, |cached_sha, _| cached_sha == sha)
+    }
+
+    /// Looks up an upload by the content hash of its source file
+    /// (`assets::content_hash`), as recorded by `insert` or `add_source`.
+    pub(crate) fn get_by_source(&self, host: &dyn ImageHost, source: &str) -> Option<UploadedFile> {
+        self.find(host, |_, cached| cached.sources.iter().any(|s| s == source))
+    }
+
+    /// Records a file that was just uploaded or found on the host.
+    pub(crate) fn insert(
+        &self,
+        target_key: &str,
+        sha: String,
+        file: &UploadedFile,
+        source: Option<&str>,
+    ) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let mut cached = CachedUpload {
+            remote_path: file.remote_path.clone(),
+            url: file.url.clone(),
+            sources: Vec::new(),
+        };
+        if let Some(source) = source {
+            add_source(&mut cached, source);
+        }
+        let state = &mut *state;
+        for entries in [&mut state.entries, &mut state.added] {
+            let files = entries.entry(target_key.to_string()).or_default();
+            let mut cached = cached.clone();
+            if let Some(known) = files
+                .get(&sha)
+                .filter(|known| known.remote_path == cached.remote_path)
+            {
+                for source in &known.sources {
+                    add_source(&mut cached, source);
+                }
+            }
+            files.insert(sha.clone(), cached);
+        }
+    }
+
+    /// Remembers `source` for a file that was already cached under `sha`.
+    pub(crate) fn add_source(&self, target_key: &str, sha: &str, source: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if let Some(cached) = state
+            .entries
+            .get_mut(target_key)
+            .and_then(|files| files.get_mut(sha))
+        {
+            add_source(cached, source);
+        }
+        state
+            .added_sources
+            .push((target_key.to_string(), sha.to_string(), source.to_string()));
+    }
+
+    /// Drops entries pointing at `remote_path`, after it was deleted, and
+    /// saves the cache.
+    pub(crate) fn forget(&self, target_key: &str, remote_path: &str) -> Result<(), String> {
+        let stale = |entries: &mut CacheEntries| {
+            if let Some(files) = entries.get_mut(target_key) {
+                files.retain(|_, cached| cached.remote_path != remote_path);
+            }
+        };
+        if let Ok(mut state) = self.state.lock() {
+            stale(&mut state.entries);
+            stale(&mut state.added);
+        }
+        self.write(stale)
+    }
+
+    /// Writes the uploads recorded since the cache was opened, keeping
+    /// entries other processes or uploads added or removed meanwhile.
+    pub(crate) fn save(&self) -> Result<(), String> {
+        self.write(|_| {})
+    }
+
+    fn write(&self, prune: impl FnOnce(&mut CacheEntries)) -> Result<(), String> {
+        let _file = lock_cache_file();
+        let mut merged = load_entries(&self.path);
+        prune(&mut merged);
+        if let Ok(state) = self.state.lock() {
+            for (target, files) in &state.added {
+                let merged_files = merged.entry(target.clone()).or_default();
+                for (sha, cached) in files {
+                    let mut cached = cached.clone();
+                    if let Some(known) = merged_files
+                        .get(sha)
+                        .filter(|known| known.remote_path == cached.remote_path)
+                    {
+                        for source in &known.sources {
+                            add_source(&mut cached, source);
+                        }
+                    }
+                    merged_files.insert(sha.clone(), cached);
+                }
+            }
+            // Only for files still cached: one deleted meanwhile stays deleted.
+            for (target, sha, source) in &state.added_sources {
+                if let Some(cached) = merged.get_mut(target).and_then(|files| files.get_mut(sha)) {
+                    add_source(cached, source);
+                }
+            }
+        }
+        let json = serde_json::to_vec_pretty(&merged).map_err(|e| e.to_string())?;
+        write_file_atomic(&self.path, &json)
+    }
+}
+
+fn load_entries(path: &Path) -> CacheEntries {
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Uploads `bytes` unless identical content is already on the host, per the
+/// cache or, with `check_remote`, the host itself. `source` is the content
+/// hash of the file `bytes` were made from, remembered for `get_by_source`.
+/// `cancelled` is checked before the upload is sent. Returns the file and
+/// whether it was reused.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn upload_deduplicated(
+    host: &dyn ImageHost,
+    cache: &UploadCache,
+    stem: &str,
+    ext: &str,
+    bytes: &[u8],
+    source: Option<&str>,
+    check_remote: bool,
+    cancelled: &AtomicBool,
+) -> Result<(UploadedFile, bool), HostError> {
+    let target_key = host.target_key();
+    let sha = git_blob_sha(bytes);
+    if let Some(file) = cache.get(host, &sha) {
+        if let Some(source) = source {
+            cache.add_source(&target_key, &sha, source);
+        }
+        return Ok((file, true));
+    }
+    let existing = if check_remote {
+        host.find_by_blob_sha(&sha)?
+    } else {
+        None
+    };
+    let reused = existing.is_some();
+    let file = match existing {
+        Some(file) => file,
+        None => {
+            upload_tasks::check_cancelled(cancelled)?;
+            host.upload(&image_host::remote_file_name(stem, ext)?, bytes)?
+        }
+    };
+    cache.insert(&target_key, sha, &file, source);
+    Ok((file, reused))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> UploadedFile {
+        UploadedFile {
+            remote_path: name.to_string(),
+            url: format!("https://cdn.example/{}", name),
+        }
+    }
+
+    #[test]
+    fn concurrent_saves_keep_every_upload() {
+        let dir = std::env::temp_dir().join(format!("carbo-upload-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("upload-cache.json");
+
+        let caches: Vec<UploadCache> = (0..8).map(|_| UploadCache::at(path.clone())).collect();
+        std::thread::scope(|scope| {
+            for (i, cache) in caches.iter().enumerate() {
+                scope.spawn(move || {
+                    cache.insert(
+                        "target",
+                        format!("sha{}", i),
+                        &file(&format!("{}.png", i)),
+                        None,
+                    );
+                    cache.save().unwrap();
+                });
+            }
+        });
+
+        let saved = load_entries(&path);
+        assert_eq!(saved["target"].len(), caches.len());
+        let leftovers: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != "upload-cache.json")
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
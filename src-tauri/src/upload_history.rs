@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::image_host::{self, HostConfig, HostError, HostErrorKind, UploadedFile};
+use crate::upload_cache::UploadCache;
+use crate::{assets, credentials};
+
+/// One JSON record per line, only ever appended to, so concurrent uploads
+/// cannot overwrite each other's entries.
+const UPLOAD_HISTORY_FILE: &str = "carbo-assets/upload-history.jsonl";
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryEntry {
+    id: String,
+    local_path: String,
+    /// The host's target key (repository, branch and folder, or bucket).
+    target: String,
+    remote_path: String,
+    url: String,
+    /// Git blob sha of the uploaded bytes.
+    sha: String,
+    /// Unix time in milliseconds.
+    uploaded_at: u64,
+    #[serde(default)]
+    document_path: Option<String>,
+    /// Identical content was already on the host, so nothing was sent.
+    #[serde(default)]
+    reused: bool,
+    #[serde(default)]
+    deleted_at: Option<u64>,
+}
+
+impl HistoryEntry {
+    pub(crate) fn new(
+        local_path: &str,
+        target: &str,
+        file: &UploadedFile,
+        sha: String,
+        document_path: Option<&str>,
+        reused: bool,
+    ) -> Self {
+        let uploaded_at = now_ms();
+        let id = assets::content_hash(
+            format!(
+                "{}\n{}\n{}\n{}",
+                uploaded_at, target, file.remote_path, local_path
+            )
+            .as_bytes(),
+        );
+        Self {
+            id,
+            local_path: local_path.to_string(),
+            target: target.to_string(),
+            remote_path: file.remote_path.clone(),
+            url: file.url.clone(),
+            sha,
+            uploaded_at,
+            document_path: document_path
+                .filter(|p| !p.trim().is_empty())
+                .map(str::to_string),
+            reused,
+            deleted_at: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum HistoryRecord {
+    Upload(HistoryEntry),
+    /// Marks every earlier upload of `remote_path` to `target` as deleted.
+    #[serde(rename_all = "camelCase")]
+    Deleted {
+        target: String,
+        remote_path: String,
+        deleted_at: u64,
+    },
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn history_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(UPLOAD_HISTORY_FILE, BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+fn append(app: &tauri::AppHandle, records: &[HistoryRecord]) -> Result<(), String> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let path = history_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record).map_err(|e| e.to_string())?;
+        lines.push(b'\n');
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| e.to_string())?;
+    // One write per call keeps lines from concurrent uploads whole.
+    file.write_all(&lines).map_err(|e| e.to_string())
+}
+
+/// Appends uploads to the history.
+pub(crate) fn record(app: &tauri::AppHandle, entries: Vec<HistoryEntry>) -> Result<(), String> {
+    let records: Vec<_> = entries.into_iter().map(HistoryRecord::Upload).collect();
+    append(app, &records)
+}
+
+/// Records that `remote_path` was removed from `target`.
+pub(crate) fn record_deleted(
+    app: &tauri::AppHandle,
+    target: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    append(
+        app,
+        &[HistoryRecord::Deleted {
+            target: target.to_string(),
+            remote_path: remote_path.to_string(),
+            deleted_at: now_ms(),
+        }],
+    )
+}
+
+/// All uploads, oldest first, with deletions applied. Unreadable lines (for
+/// example one cut short by a crash) are skipped.
+fn load(app: &tauri::AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let text = match std::fs::read_to_string(history_path(app)?) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut by_file: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(HistoryRecord::Upload(entry)) => {
+                by_file
+                    .entry((entry.target.clone(), entry.remote_path.clone()))
+                    .or_default()
+                    .push(entries.len());
+                entries.push(entry);
+            }
+            Ok(HistoryRecord::Deleted {
+                target,
+                remote_path,
+                deleted_at,
+            }) => {
+                // A later upload to the same path is a new file; keep it.
+                for index in by_file.remove(&(target, remote_path)).unwrap_or_default() {
+                    entries[index].deleted_at.get_or_insert(deleted_at);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct UploadHistoryQuery {
+    /// Case-insensitive text matched against paths, URLs and the sha.
+    pub search: Option<String>,
+    /// Only uploads to this target key.
+    pub target: Option<String>,
+    pub document_path: Option<String>,
+    pub include_deleted: bool,
+    pub offset: usize,
+    /// Defaults to 100.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UploadHistoryPage {
+    /// Newest first.
+    entries: Vec<HistoryEntry>,
+    /// Matching entries before paging.
+    total: usize,
+}
+
+fn matches(entry: &HistoryEntry, query: &UploadHistoryQuery, search: &str) -> bool {
+    if entry.deleted_at.is_some() && !query.include_deleted {
+        return false;
+    }
+    if query.target.as_ref().is_some_and(|t| *t != entry.target) {
+        return false;
+    }
+    if query
+        .document_path
+        .as_ref()
+        .is_some_and(|d| entry.document_path.as_ref() != Some(d))
+    {
+        return false;
+    }
+    search.is_empty()
+        || [
+            Some(&entry.local_path),
+            Some(&entry.remote_path),
+            Some(&entry.url),
+            entry.document_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(search))
+        || entry.sha.starts_with(search)
+}
+
+/// Lists uploads, newest first, optionally filtered by `query.search`.
+#[tauri::command]
+pub(crate) fn list_upload_history(
+    app: tauri::AppHandle,
+    query: Option<UploadHistoryQuery>,
+) -> Result<UploadHistoryPage, String> {
+    let query = query.unwrap_or_default();
+    let search = query.search.as_deref().unwrap_or("").trim().to_lowercase();
+    let matching: Vec<HistoryEntry> = load(&app)?
+        .into_iter()
+        .rev()
+        .filter(|entry| matches(entry, &query, &search))
+        .collect();
+    let total = matching.len();
+    let entries = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .collect();
+    Ok(UploadHistoryPage { entries, total })
+}
+
+/// Deletes the file behind history entry `id` from `host`, which must be the
+/// host it was uploaded to, and marks it deleted. GitHub refuses when the file
+/// has changed since the upload. A file that is already gone is only marked.
+#[tauri::command]
+pub(crate) async fn delete_uploaded_image(
+    app: tauri::AppHandle,
+    mut host: HostConfig,
+    id: String,
+    timeout_secs: Option<u64>,
+) -> Result<HistoryEntry, HostError> {
+    image_host::run_blocking(move || {
+        let mut entry = load(&app)?
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| {
+                HostError::new(HostErrorKind::NotFound, format!("no upload with id {}", id))
+            })?;
+        if entry.deleted_at.is_some() {
+            return Ok(entry);
+        }
+
+        credentials::fill_host_secret(&app, &mut host)?;
+        let host = image_host::build_host(&app, &host, timeout_secs)?;
+        if host.target_key() != entry.target {
+            return Err(HostError::new(
+                HostErrorKind::Other,
+                format!("this image was uploaded to {}", entry.target),
+            ));
+        }
+        match host.delete_blob(&entry.remote_path, &entry.sha) {
+            Ok(()) => {}
+            Err(error) if error.kind == HostErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        UploadCache::open(&app)?.forget(&entry.target, &entry.remote_path)?;
+        record_deleted(&app, &entry.target, &entry.remote_path)?;
+        entry.deleted_at = Some(now_ms());
+        Ok(entry)
+    })
+    .await
+}
@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{path::BaseDirectory, Emitter, Manager};
+
+use crate::image_host::{HostConfig, HostError, HostErrorKind, UploadOptions};
+use crate::{assets, markdown_images, upload_cache, upload_tasks};
+
+const UPLOAD_QUEUE_FILE: &str = "carbo-assets/upload-queue.json";
+/// First retry delay, doubled per failed attempt up to `MAX_RETRY_DELAY`.
+const RETRY_BASE: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// An upload waiting for the host to become reachable. Times are Unix
+/// milliseconds.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QueuedUpload {
+    id: String,
+    /// Stored without its token or secret; the worker uses the stored one.
+    host: HostConfig,
+    document_path: String,
+    /// Link as written in the document, replaced by the hosted URL once the
+    /// upload succeeds.
+    local_link: String,
+    local_path: String,
+    max_bytes: Option<u64>,
+    options: UploadOptions,
+    queued_at: u64,
+    attempts: u32,
+    next_attempt_at: u64,
+    last_error: Option<String>,
+    /// Retrying cannot help (bad token, missing file) until the user acts.
+    failed: bool,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum QueueStatus {
+    Queued,
+    Retrying,
+    Uploaded,
+    Failed,
+}
+
+/// Payload of the `carbo-upload-queue` event. On `uploaded`, open documents
+/// should replace `localLink` with `url`.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct QueueEvent {
+    job_id: String,
+    document_path: String,
+    local_link: String,
+    local_path: String,
+    status: QueueStatus,
+    url: Option<String>,
+    error: Option<String>,
+    next_attempt_at: Option<u64>,
+}
+
+impl QueueEvent {
+    fn new(job: &QueuedUpload, status: QueueStatus) -> Self {
+        Self {
+            job_id: job.id.clone(),
+            document_path: job.document_path.clone(),
+            local_link: job.local_link.clone(),
+            local_path: job.local_path.clone(),
+            status,
+            url: None,
+            error: job.last_error.clone(),
+            next_attempt_at: None,
+        }
+    }
+}
+
+/// Uploads saved to `$APPDATA/carbo-assets/upload-queue.json`, worked off by
+/// one background thread.
+pub(crate) struct UploadQueue {
+    path: PathBuf,
+    jobs: Mutex<Vec<QueuedUpload>>,
+    /// Signalled when jobs are added or made due.
+    wake: Condvar,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn retry_delay(attempts: u32, error: &HostError) -> Duration {
+    if let Some(secs) = error.retry_after_secs {
+        return Duration::from_secs(secs).min(MAX_RETRY_DELAY);
+    }
+    RETRY_BASE
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Whether the upload may succeed later without the user changing anything.
+fn is_transient(error: &HostError) -> bool {
+    matches!(
+        error.kind,
+        HostErrorKind::Network
+            | HostErrorKind::Server
+            | HostErrorKind::RateLimited
+            | HostErrorKind::Conflict
+    )
+}
+
+impl UploadQueue {
+    fn load(app: &tauri::AppHandle) -> Result<Self, String> {
+        let path = app
+            .path()
+            .resolve(UPLOAD_QUEUE_FILE, BaseDirectory::AppData)
+            .map_err(|e| e.to_string())?;
+        let jobs = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Ok(Self {
+            path,
+            jobs: Mutex::new(jobs),
+            wake: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Vec<QueuedUpload>>, String> {
+        self.jobs
+            .lock()
+            .map_err(|_| "upload queue lock poisoned".to_string())
+    }
+
+    /// Writes the queue file. Taking the guard means saves are serialized by
+    /// the queue's lock, and the rename keeps a crash from truncating the file.
+    fn save(&self, jobs: &MutexGuard<'_, Vec<QueuedUpload>>) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(&**jobs).map_err(|e| e.to_string())?;
+        upload_cache::write_file_atomic(&self.path, &json)
+    }
+
+    /// Blocks until a job is due and returns it.
+    fn wait_for_due(&self) -> Option<QueuedUpload> {
+        let mut jobs = self.jobs.lock().ok()?;
+        loop {
+            let now = now_ms();
+            let pending = jobs.iter().filter(|job| !job.failed);
+            if let Some(job) = pending.clone().find(|job| job.next_attempt_at <= now) {
+                return Some(job.clone());
+            }
+            jobs = match pending.map(|job| job.next_attempt_at - now).min() {
+                Some(wait) => {
+                    self.wake
+                        .wait_timeout(jobs, Duration::from_millis(wait))
+                        .ok()?
+                        .0
+                }
+                None => self.wake.wait(jobs).ok()?,
+            };
+        }
+    }
+
+    fn finish(
+        &self,
+        app: &tauri::AppHandle,
+        mut job: QueuedUpload,
+        result: Result<String, HostError>,
+    ) {
+        let Ok(mut jobs) = self.jobs.lock() else {
+            return;
+        };
+        let position = jobs.iter().position(|queued| queued.id == job.id);
+        let event = match result {
+            Ok(url) => {
+                if let Some(position) = position {
+                    jobs.remove(position);
+                }
+                QueueEvent {
+                    url: Some(url),
+                    error: None,
+                    ..QueueEvent::new(&job, QueueStatus::Uploaded)
+                }
+            }
+            Err(error) => {
+                // Removed by the user while the upload was running.
+                let Some(position) = position else {
+                    return;
+                };
+                job.attempts += 1;
+                job.last_error = Some(error.message.clone());
+                let status = if is_transient(&error) {
+                    let delay = retry_delay(job.attempts, &error);
+                    job.next_attempt_at = now_ms() + delay.as_millis() as u64;
+                    QueueStatus::Retrying
+                } else {
+                    job.failed = true;
+                    QueueStatus::Failed
+                };
+                jobs[position] = job.clone();
+                QueueEvent {
+                    next_attempt_at: (!job.failed).then_some(job.next_attempt_at),
+                    ..QueueEvent::new(&job, status)
+                }
+            }
+        };
+        let _ = self.save(&jobs);
+        drop(jobs);
+        let _ = app.emit("carbo-upload-queue", event);
+    }
+}
+
+/// Loads the queue and starts its worker thread.
+pub(crate) fn start(app: &tauri::AppHandle) -> Result<(), String> {
+    app.manage(UploadQueue::load(app)?);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let queue = app.state::<UploadQueue>();
+        while let Some(job) = queue.wait_for_due() {
+            let result = upload_tasks::upload_file(
+                &app,
+                &job.host,
+                &job.local_path,
+                job.max_bytes.unwrap_or(u64::MAX),
+                &job.options,
+            )
+            .map(|uploaded| uploaded.url);
+            queue.finish(&app, job, result);
+        }
+    });
+    Ok(())
+}
+
+/// Queues an upload of an image already saved next to the document (see
+/// `save_image_for_document`), to be retried until the host can be reached.
+/// The host's token is not written to disk, so it must be stored with
+/// `set_host_token`. Progress is reported through `carbo-upload-queue`.
+#[tauri::command]
+pub(crate) fn queue_image_upload(
+    app: tauri::AppHandle,
+    queue: tauri::State<'_, UploadQueue>,
+    mut host: HostConfig,
+    document_path: String,
+    local_link: String,
+    max_bytes: Option<u64>,
+    options: Option<UploadOptions>,
+) -> Result<QueuedUpload, String> {
+    let local_path =
+        markdown_images::resolve_local_reference(&local_link, Path::new(&document_path))
+            .ok_or_else(|| format!("{} is not a local image", local_link))?;
+    if !crate::is_allowed_image_extension(&local_path) || !local_path.is_file() {
+        return Err(format!("{} is not a local image", local_link));
+    }
+    if let Some((_, secret)) = host.secret_mut() {
+        secret.clear();
+    }
+    let mut options = options.unwrap_or_default();
+    options
+        .document_path
+        .get_or_insert_with(|| document_path.clone());
+
+    let local_path = local_path.to_string_lossy().to_string();
+    let now = now_ms();
+    let job = QueuedUpload {
+        id: format!(
+            "{}-{}",
+            now,
+            assets::content_hash(format!("{}\n{}", document_path, local_path).as_bytes())
+        ),
+        host,
+        document_path,
+        local_link,
+        local_path,
+        max_bytes,
+        options,
+        queued_at: now,
+        attempts: 0,
+        next_attempt_at: now,
+        last_error: None,
+        failed: false,
+    };
+
+    let mut jobs = queue.lock()?;
+    jobs.push(job.clone());
+    queue.save(&jobs)?;
+    drop(jobs);
+    queue.wake.notify_all();
+    let _ = app.emit(
+        "carbo-upload-queue",
+        QueueEvent::new(&job, QueueStatus::Queued),
+    );
+    Ok(job)
+}
+
+#[tauri::command]
+pub(crate) fn list_queued_uploads(
+    queue: tauri::State<'_, UploadQueue>,
+) -> Result<Vec<QueuedUpload>, String> {
+    Ok(queue.lock()?.clone())
+}
+
+/// Makes queued uploads due now, including failed ones, e.g. when the
+/// network comes back or a token was stored. Without `id`, retries all.
+/// Returns how many jobs were affected.
+#[tauri::command]
+pub(crate) fn retry_queued_uploads(
+    queue: tauri::State<'_, UploadQueue>,
+    id: Option<String>,
+) -> Result<usize, String> {
+    let mut jobs = queue.lock()?;
+    let now = now_ms();
+    let mut count = 0;
+    for job in jobs
+        .iter_mut()
+        .filter(|job| id.as_ref().is_none_or(|id| *id == job.id))
+    {
+        job.failed = false;
+        job.next_attempt_at = now;
+        count += 1;
+    }
+    queue.save(&jobs)?;
+    drop(jobs);
+    queue.wake.notify_all();
+    Ok(count)
+}
+
+/// Drops a queued upload. The local image and its link are left as they are.
+#[tauri::command]
+pub(crate) fn remove_queued_upload(
+    queue: tauri::State<'_, UploadQueue>,
+    id: String,
+) -> Result<bool, String> {
+    let mut jobs = queue.lock()?;
+    let before = jobs.len();
+    jobs.retain(|job| job.id != id);
+    if jobs.len() == before {
+        return Ok(false);
+    }
+    queue.save(&jobs)?;
+    Ok(true)
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::credentials;
+use crate::image_host::{self, HostConfig, HostError, HostErrorKind, UploadOptions, UploadedFile};
+use crate::upload_cache::{self, UploadCache};
+use crate::upload_history::{self, HistoryEntry};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum UploadStage {
+    /// Reading, validating and re-encoding the image.
+    Encoding,
+    /// Sending it to the host.
+    Uploading,
+    Done,
+    /// Cancelled once the host already had the file; `url` points at it.
+    CompletedBeforeCancel,
+    Failed,
+    Cancelled,
+}
+
+/// Payload of the `carbo-upload-status` event.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UploadStatus {
+    upload_id: String,
+    local_path: String,
+    stage: UploadStage,
+    url: Option<String>,
+    error: Option<String>,
+}
+
+/// Uploads in flight, by id, with their cancellation flags.
+#[derive(Default)]
+pub(crate) struct UploadRegistry {
+    active: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    next_id: AtomicU64,
+}
+
+impl UploadRegistry {
+    /// Registers an upload under `upload_id`, or a generated id, and returns
+    /// the id with the flag `cancel_image_upload` sets.
+    pub(crate) fn register(
+        &self,
+        upload_id: Option<String>,
+    ) -> Result<(String, Arc<AtomicBool>), String> {
+        let id = upload_id
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| format!("upload-{}", self.next_id.fetch_add(1, Ordering::Relaxed)));
+        let mut active = self
+            .active
+            .lock()
+            .map_err(|_| "upload registry lock poisoned".to_string())?;
+        if active.contains_key(&id) {
+            return Err(format!("upload {} is already running", id));
+        }
+        let flag = Arc::new(AtomicBool::new(false));
+        active.insert(id.clone(), flag.clone());
+        Ok((id, flag))
+    }
+
+    pub(crate) fn finish(&self, upload_id: &str) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(upload_id);
+        }
+    }
+
+    fn cancel(&self, upload_id: &str) -> bool {
+        self.active
+            .lock()
+            .ok()
+            .and_then(|active| active.get(upload_id).cloned())
+            .map(|flag| flag.store(true, Ordering::SeqCst))
+            .is_some()
+    }
+}
+
+/// Fails with a `Cancelled` error once `cancelled` is set.
+pub(crate) fn check_cancelled(cancelled: &AtomicBool) -> Result<(), HostError> {
+    if cancelled.load(Ordering::SeqCst) {
+        Err(HostError::new(HostErrorKind::Cancelled, "upload cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Uploads `local_path` on a blocking thread, emitting `carbo-upload-status`
+/// for each stage.
+pub(crate) async fn run_upload(
+    app: tauri::AppHandle,
+    registry: &UploadRegistry,
+    upload_id: Option<String>,
+    host: HostConfig,
+    local_path: String,
+    max_bytes: u64,
+    options: UploadOptions,
+) -> Result<UploadedFile, HostError> {
+    let (id, cancelled) = registry.register(upload_id)?;
+    let task_id = id.clone();
+    let result = image_host::run_blocking(move || {
+        let emit = |stage, url: Option<String>, error: Option<String>| {
+            let _ = app.emit(
+                "carbo-upload-status",
+                UploadStatus {
+                    upload_id: task_id.clone(),
+                    local_path: local_path.clone(),
+                    stage,
+                    url,
+                    error,
+                },
+            );
+        };
+        let result = upload_stages(
+            &app,
+            &host,
+            &local_path,
+            max_bytes,
+            &options,
+            &cancelled,
+            &|s| emit(s, None, None),
+        );
+        match &result {
+            Ok(uploaded) if cancelled.load(Ordering::SeqCst) => emit(
+                UploadStage::CompletedBeforeCancel,
+                Some(uploaded.url.clone()),
+                None,
+            ),
+            Ok(uploaded) => emit(UploadStage::Done, Some(uploaded.url.clone()), None),
+            Err(error) if error.kind == HostErrorKind::Cancelled => {
+                emit(UploadStage::Cancelled, None, Some(error.message.clone()))
+            }
+            Err(error) => emit(UploadStage::Failed, None, Some(error.message.clone())),
+        }
+        result
+    })
+    .await;
+    registry.finish(&id);
+    result
+}
+
+/// Uploads `local_path` on the current thread, without progress events.
+pub(crate) fn upload_file(
+    app: &tauri::AppHandle,
+    host: &HostConfig,
+    local_path: &str,
+    max_bytes: u64,
+    options: &UploadOptions,
+) -> Result<UploadedFile, HostError> {
+    upload_stages(
+        app,
+        host,
+        local_path,
+        max_bytes,
+        options,
+        &Arc::default(),
+        &|_| {},
+    )
+}
+
+fn upload_stages(
+    app: &tauri::AppHandle,
+    host: &HostConfig,
+    local_path: &str,
+    max_bytes: u64,
+    options: &UploadOptions,
+    cancelled: &Arc<AtomicBool>,
+    stage: &dyn Fn(UploadStage),
+) -> Result<UploadedFile, HostError> {
+    stage(UploadStage::Encoding);
+    let src = Path::new(local_path);
+    let original = image_host::read_upload_source(src)?;
+    let (bytes, ext) = image_host::prepare_upload_bytes(src, original, max_bytes, options)?;
+    check_cancelled(cancelled)?;
+
+    stage(UploadStage::Uploading);
+    let mut host = host.clone();
+    credentials::fill_host_secret(app, &mut host)?;
+    let host = image_host::build_cancellable_host(app, &host, options.timeout_secs, cancelled)?;
+    check_cancelled(cancelled)?;
+    let stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let cache = UploadCache::open(app)?;
+    let (uploaded, reused) = upload_cache::upload_deduplicated(
+        host.as_ref(),
+        &cache,
+        stem,
+        &ext,
+        &bytes,
+        None,
+        options.check_remote,
+        cancelled,
+    )?;
+
+    // A request already on the wire cannot be recalled. Deleting the file
+    // could fail or race with a document that already links it, so a late
+    // cancel keeps the upload and is reported as completed.
+    cache.save()?;
+    upload_history::record(
+        app,
+        vec![HistoryEntry::new(
+            local_path,
+            &host.target_key(),
+            &uploaded,
+            upload_cache::git_blob_sha(&bytes),
+            options.document_path.as_deref(),
+            reused,
+        )],
+    )?;
+    Ok(uploaded)
+}
+
+/// Cancels a running upload or GitHub batch. Returns false when no upload
+/// has that id. An upload whose request is already in flight still
+/// completes and is reported as `completedBeforeCancel`.
+#[tauri::command]
+pub(crate) fn cancel_image_upload(
+    registry: tauri::State<'_, UploadRegistry>,
+    upload_id: String,
+) -> Result<bool, String> {
+    Ok(registry.cancel(&upload_id))
+}
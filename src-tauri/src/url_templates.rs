@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::markdown_images;
+
+/// Replaces each `{name}` in `template` with its value. Unknown placeholders
+/// are left as they are.
+pub(crate) fn expand(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = template.trim().to_string();
+    for (name, value) in values {
+        out = out.replace(&format!("{{{}}}", name), value);
+    }
+    out
+}
+
+enum Part<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template.trim();
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Literal(&rest[..open]));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in {}", template))?;
+        let name = &rest[open + 1..open + close];
+        if name.is_empty() {
+            return Err(format!("empty placeholder in {}", template));
+        }
+        if matches!(parts.last(), Some(Part::Placeholder(_))) {
+            return Err(format!(
+                "placeholders in {} must be separated by text",
+                template
+            ));
+        }
+        parts.push(Part::Placeholder(name));
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    Ok(parts)
+}
+
+/// Matches `url` against `template`, returning the placeholder values.
+/// `{path}` may span several segments; other placeholders match within one.
+fn capture(parts: &[Part<'_>], url: &str) -> Option<HashMap<String, String>> {
+    let mut values: HashMap<String, String> = HashMap::new();
+    let mut rest = url;
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Literal(literal) => rest = rest.strip_prefix(literal)?,
+            Part::Placeholder(name) => {
+                let value = match parts.get(i + 1) {
+                    Some(Part::Literal(next)) if *name == "path" => &rest[..rest.rfind(next)?],
+                    Some(Part::Literal(next)) => &rest[..rest.find(next)?],
+                    _ => rest,
+                };
+                if value.is_empty() || (*name != "path" && value.contains('/')) {
+                    return None;
+                }
+                match values.get(*name) {
+                    Some(previous) if previous != value => return None,
+                    Some(_) => {}
+                    None => {
+                        values.insert(name.to_string(), value.to_string());
+                    }
+                }
+                rest = &rest[value.len()..];
+            }
+        }
+    }
+    rest.is_empty().then_some(values)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RewrittenLink {
+    from: String,
+    to: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RewriteLinksResult {
+    text: String,
+    rewritten: Vec<RewrittenLink>,
+}
+
+/// Rewrites image links matching `from_template` to `to_template`, e.g. from
+/// `https://raw.githubusercontent.com/{owner}/{repo}/{branch}/{path}` to
+/// `https://cdn.jsdelivr.net/gh/{owner}/{repo}@{branch}/{path}`. `text`
+/// defaults to the file on disk; the document itself is not written.
+#[tauri::command]
+pub(crate) fn rewrite_image_links(
+    document_path: String,
+    text: Option<String>,
+    from_template: String,
+    to_template: String,
+) -> Result<RewriteLinksResult, String> {
+    let text = match text {
+        Some(text) => text,
+        None => std::fs::read_to_string(Path::new(&document_path)).map_err(|e| e.to_string())?,
+    };
+    let from = parse(&from_template)?;
+    for part in parse(&to_template)? {
+        if let Part::Placeholder(name) = part {
+            if !from
+                .iter()
+                .any(|p| matches!(p, Part::Placeholder(n) if *n == name))
+            {
+                return Err(format!("{{{}}} is not in {}", name, from_template));
+            }
+        }
+    }
+
+    let mut rewritten = Vec::new();
+    let mut out = text.clone();
+    for reference in markdown_images::find_image_references(&text).iter().rev() {
+        let Some(values) = capture(&from, &reference.url) else {
+            continue;
+        };
+        let values: Vec<(&str, &str)> = values
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = expand(&to_template, &values);
+        if url != reference.url {
+            out.replace_range(reference.start..reference.end, &url);
+            rewritten.push(RewrittenLink {
+                from: reference.url.clone(),
+                to: url,
+            });
+        }
+    }
+    rewritten.reverse();
+    Ok(RewriteLinksResult {
+        text: out,
+        rewritten,
+    })
+}
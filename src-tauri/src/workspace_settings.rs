@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_DIR: &str = ".carbo";
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Per-workspace defaults, stored in `<workspace>/.carbo/settings.json` so they
+/// travel with the workspace. Unset fields fall back to the built-in defaults.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct WorkspaceSettings {
+    /// Strip EXIF/GPS metadata and bake in orientation when images are ingested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_image_metadata: Option<bool>,
+}
+
+fn settings_path(workspace: &Path) -> PathBuf {
+    workspace.join(SETTINGS_DIR).join(SETTINGS_FILE)
+}
+
+fn load(path: &Path) -> Result<WorkspaceSettings, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// Settings of the nearest workspace containing `path`, found by walking up
+/// the directory tree. Returns the defaults when no workspace settings exist.
+pub(crate) fn settings_for_path(path: &Path) -> WorkspaceSettings {
+    for dir in path.ancestors().skip(1) {
+        let candidate = settings_path(dir);
+        if candidate.is_file() {
+            return load(&candidate).unwrap_or_default();
+        }
+    }
+    WorkspaceSettings::default()
+}
+
+/// Resolves whether image metadata should be stripped for a file ingested at
+/// `path`: an explicit request wins, then the workspace default, then off.
+pub(crate) fn resolve_strip_metadata(explicit: Option<bool>, path: Option<&Path>) -> bool {
+    explicit
+        .or_else(|| path.and_then(|p| settings_for_path(p).strip_image_metadata))
+        .unwrap_or(false)
+}
+
+#[tauri::command]
+pub(crate) fn read_workspace_settings(workspace_path: String) -> Result<WorkspaceSettings, String> {
+    let path = settings_path(Path::new(&workspace_path));
+    if !path.is_file() {
+        return Ok(WorkspaceSettings::default());
+    }
+    load(&path)
+}
+
+#[tauri::command]
+pub(crate) fn write_workspace_settings(
+    workspace_path: String,
+    settings: WorkspaceSettings,
+) -> Result<(), String> {
+    let workspace = PathBuf::from(workspace_path);
+    if !workspace.is_dir() {
+        return Err("not a directory".to_string());
+    }
+    let path = settings_path(&workspace);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let text = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+    std::fs::write(&path, text).map_err(|e| e.to_string())
+}